@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{Opcode, RAM_SIZE, START_ADDR};
+
+// static analysis over rom bytes, nothing here executes code
+
+// run of instructions with one way in at the top and control flow only leaving at the bottom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: u16, // address of the first instruction
+    pub end: u16, // address just past the last instruction
+}
+
+// control flow graph of everything reachable from START_ADDR
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Cfg {
+    pub blocks: Vec<BasicBlock>, // sorted by address
+    pub edges: Vec<(u16, u16)>, // (from block start, to block start), a CALL has edges to both the routine and its return point
+}
+
+// opcode stored at a ram address, None if it falls outside the rom or past the end of ram
+// bytes that could never be loaded are ignored, which also keeps addr + 2 and addr + 4 from overflowing
+fn opcode_at(bytes: &[u8], addr: u16) -> Option<u16> {
+    if addr as usize + 2 > RAM_SIZE {
+        return None;
+    }
+    let offset = (addr as usize).checked_sub(START_ADDR as usize)?;
+    let higher_byte = *bytes.get(offset)? as u16;
+    let lower_byte = *bytes.get(offset + 1)? as u16;
+    Some((higher_byte << 8) | lower_byte)
+}
+
+// where control can go after the instruction at addr, BNNN depends on V0 so it has none we can know
+fn successors(addr: u16, decoded: Opcode) -> Vec<u16> {
+    let next = addr + 2;
+    match decoded {
+        Opcode::Jmp(target) => vec![target],
+        Opcode::Call(target) => vec![target, next],
+        Opcode::Ret | Opcode::JmpV0(_) => vec![],
+        Opcode::SkipEqImm { .. } | Opcode::SkipNeImm { .. } | Opcode::SkipEqReg { .. } | Opcode::SkipNeReg { .. } |
+        Opcode::SkipKey(_) | Opcode::SkipNotKey(_) => vec![next, next + 2],
+        _ => vec![next]
+    }
+}
+
+// anything that isn't a plain fall through to the next instruction ends a basic block
+fn ends_block(decoded: Opcode) -> bool {
+    matches!(decoded,
+        Opcode::Jmp(_) | Opcode::Call(_) | Opcode::Ret | Opcode::JmpV0(_) |
+        Opcode::SkipEqImm { .. } | Opcode::SkipNeImm { .. } | Opcode::SkipEqReg { .. } | Opcode::SkipNeReg { .. } |
+        Opcode::SkipKey(_) | Opcode::SkipNotKey(_))
+}
+
+// every instruction reachable from START_ADDR, stopping at bytes that don't decode
+fn reachable(bytes: &[u8]) -> BTreeMap<u16, Opcode> {
+    let mut found = BTreeMap::new();
+    let mut pending = vec![START_ADDR];
+    while let Some(addr) = pending.pop() {
+        if found.contains_key(&addr) {
+            continue;
+        }
+        let decoded = match opcode_at(bytes, addr).and_then(Opcode::decode) {
+            Some(decoded) => decoded,
+            None => continue // outside the rom or not something we can execute, most likely data
+        };
+        found.insert(addr, decoded);
+        pending.extend(successors(addr, decoded));
+    }
+    found
+}
+
+// best effort guess at where a rom's code stops and trailing data starts, as a byte length into bytes
+// follows every jump, call and skip reachable from the start address and returns the end of the furthest instruction found
+// BNNN jumps depend on V0 so aren't followed, and code only reached through them will be treated as data
+pub fn estimate_code_end(bytes: &[u8]) -> usize {
+    match reachable(bytes).keys().next_back() {
+        Some(&last) => (last - START_ADDR) as usize + 2,
+        None => 0
+    }
+}
+
+// split the reachable code into basic blocks at jumps, calls, returns, skips and anything they target
+pub fn build_cfg(bytes: &[u8]) -> Cfg {
+    let instructions = reachable(bytes);
+
+    // block leaders are the entry point, every branch target and whatever follows a block ending instruction
+    let mut leaders = BTreeSet::new();
+    leaders.insert(START_ADDR);
+    for (&addr, &decoded) in &instructions {
+        if ends_block(decoded) {
+            leaders.extend(successors(addr, decoded));
+            leaders.insert(addr + 2);
+        }
+    }
+
+    let mut cfg = Cfg::default();
+    let mut current: Option<BasicBlock> = None;
+    for (&addr, &decoded) in &instructions {
+        // a gap in the instruction stream or a leader starts a new block
+        let block = match current {
+            Some(block) if block.end == addr && !leaders.contains(&addr) => BasicBlock { end: addr + 2, ..block },
+            Some(block) => {
+                cfg.edges.extend(fall_through_edge(&instructions, block));
+                cfg.blocks.push(block);
+                BasicBlock { start: addr, end: addr + 2 }
+            },
+            None => BasicBlock { start: addr, end: addr + 2 }
+        };
+        if ends_block(decoded) {
+            for target in successors(addr, decoded) {
+                if instructions.contains_key(&target) {
+                    cfg.edges.push((block.start, target));
+                }
+            }
+            cfg.blocks.push(block);
+            current = None;
+        } else {
+            current = Some(block);
+        }
+    }
+    if let Some(block) = current {
+        cfg.edges.extend(fall_through_edge(&instructions, block));
+        cfg.blocks.push(block);
+    }
+    cfg
+}
+
+// a block cut short by the next leader falls through into it
+fn fall_through_edge(instructions: &BTreeMap<u16, Opcode>, block: BasicBlock) -> Option<(u16, u16)> {
+    instructions.contains_key(&block.end).then_some((block.start, block.end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_end_excludes_trailing_data() {
+        // LD V1, 1 / JMP 0x202 followed by a 4 byte table
+        let rom = [0x61, 0x01, 0x12, 0x02, 0xFF, 0x81, 0x42, 0x00];
+        assert_eq!(estimate_code_end(&rom), 4);
+    }
+
+    #[test]
+    fn code_end_follows_skips_and_calls() {
+        // SE V0, 0 / JMP 0x208 / CALL 0x20A / data / RET
+        let rom = [0x30, 0x00, 0x12, 0x08, 0x22, 0x0A, 0xFF, 0xFF, 0x00, 0xEE, 0x00, 0xEE];
+        assert_eq!(estimate_code_end(&rom), 12);
+    }
+
+    #[test]
+    fn code_end_stops_at_the_end_of_ram() {
+        // straight line NOPs far longer than ram, nothing past 0xFFF can be code
+        let rom = vec![0; 0x10000];
+        assert_eq!(estimate_code_end(&rom), RAM_SIZE - START_ADDR as usize);
+    }
+
+    #[test]
+    fn cfg_has_the_loop_back_edge() {
+        // 200: LD V0, 0 / 202: ADD V0, 1 / 204: SE V0, 10 / 206: JMP 0x202 / 208: JMP 0x208
+        let rom = [0x60, 0x00, 0x70, 0x01, 0x30, 0x0A, 0x12, 0x02, 0x12, 0x08];
+        let cfg = build_cfg(&rom);
+        assert_eq!(cfg.blocks, vec![
+            BasicBlock { start: 0x200, end: 0x202 },
+            BasicBlock { start: 0x202, end: 0x206 },
+            BasicBlock { start: 0x206, end: 0x208 },
+            BasicBlock { start: 0x208, end: 0x20A },
+        ]);
+        assert!(cfg.edges.contains(&(0x206, 0x202)), "back edge of the loop");
+        assert!(cfg.edges.contains(&(0x200, 0x202)), "fall through into the loop head");
+        assert!(cfg.edges.contains(&(0x202, 0x206)) && cfg.edges.contains(&(0x202, 0x208)), "both sides of the skip");
+        assert!(cfg.edges.contains(&(0x208, 0x208)));
+    }
+}
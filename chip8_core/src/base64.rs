@@ -0,0 +1,63 @@
+use crate::Chip8Error;
+
+// small standard alphabet base64 decoder so embedding roms doesn't pull in a dependency
+// whitespace is skipped so wrapped strings work, '=' padding is optional
+pub fn decode(s: &str) -> Result<Vec<u8>, Chip8Error> {
+    let digits: Vec<u8> = s.bytes().filter(|c| !c.is_ascii_whitespace()).collect();
+    let digits = match digits.iter().position(|&c| c == b'=') {
+        Some(pad) => {
+            // padding only ever appears as one or two '=' making up the end of the last group of 4
+            let valid = digits[pad..].iter().all(|&c| c == b'=') && digits.len().is_multiple_of(4) && digits.len() - pad <= 2;
+            if !valid {
+                return Err(Chip8Error::BadEncoding);
+            }
+            &digits[..pad]
+        },
+        None => &digits[..]
+    };
+    if digits.len() % 4 == 1 {
+        return Err(Chip8Error::BadEncoding); // a lone trailing digit only holds 6 bits, not a whole byte
+    }
+
+    let mut out = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+        let mut bits = 0u32;
+        for &c in chunk {
+            bits = (bits << 6) | value(c)? as u32;
+        }
+        bits <<= 6 * (4 - chunk.len()) as u32; // line a short final chunk up as if it were padded
+        let bytes = bits.to_be_bytes();
+        out.extend_from_slice(&bytes[1..chunk.len()]);
+    }
+    Ok(out)
+}
+
+fn value(c: u8) -> Result<u8, Chip8Error> {
+    match c {
+        b'A'..=b'Z' => Ok(c - b'A'),
+        b'a'..=b'z' => Ok(c - b'a' + 26),
+        b'0'..=b'9' => Ok(c - b'0' + 52),
+        b'+' => Ok(62),
+        b'/' => Ok(63),
+        _ => Err(Chip8Error::BadEncoding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_with_and_without_padding() {
+        assert_eq!(decode("AOASAA==").unwrap(), [0x00, 0xE0, 0x12, 0x00]);
+        assert_eq!(decode("AOASAA").unwrap(), [0x00, 0xE0, 0x12, 0x00]);
+        assert_eq!(decode("AOA\nSAA==").unwrap(), [0x00, 0xE0, 0x12, 0x00]);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!(decode("AOASA"), Err(Chip8Error::BadEncoding));
+        assert_eq!(decode("AO=SAA=="), Err(Chip8Error::BadEncoding));
+        assert_eq!(decode("AOAS!A=="), Err(Chip8Error::BadEncoding));
+    }
+}
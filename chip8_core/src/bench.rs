@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::{Chip8Error, Emulator};
+
+// timing and instruction mix from one bench_rom run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchResult {
+    pub cycles: usize, // instructions actually executed, only short of the request if the rom halted
+    pub elapsed: Duration, // wall time spent in tick, setup excluded
+    pub opcode_histogram: HashMap<u8, u64>, // same as Emulator::opcode_histogram
+}
+
+// run a rom for exactly cycles instructions from a fresh emulator, for tracking execute's performance
+// the rng is seeded and no keys or timers are touched so the same rom, cycles and seed always do the same work
+pub fn bench_rom(bytes: &[u8], cycles: usize, seed: u64) -> Result<BenchResult, Chip8Error> {
+    let mut emu = Emulator::from_rom(bytes)?;
+    emu.set_rng_seed(seed);
+    emu.set_opcode_histogram_enabled(true);
+
+    let start = Instant::now();
+    for _ in 0..cycles {
+        emu.tick()?;
+    }
+    let elapsed = start.elapsed();
+
+    Ok(BenchResult {
+        cycles: emu.total_cycles() as usize,
+        elapsed,
+        opcode_histogram: emu.opcode_histogram()
+    })
+}
@@ -0,0 +1,178 @@
+use std::fmt::Write;
+
+use crate::{disassemble, Emulator, NUM_REGISTERS, RAM_SIZE};
+
+const CONTINUE_MAX_CYCLES: usize = 1_000_000; // give up on continue eventually so a missed breakpoint can't hang the tool
+const MEM_BYTES_PER_LINE: usize = 16;
+
+// gdb style command processor, a cli frontend forwards each line typed and prints the reply
+// addresses and lengths are hex without a prefix, e.g. "mem 200 20" dumps 0x20 bytes from 0x200
+// instruction counts are decimal, "step 10" runs ten
+//   step [n]        run n instructions (default 1) and show the next one
+//   regs            registers, timers and stack pointer
+//   mem ADDR [LEN]  hex dump of ram
+//   dis [n]         disassemble the next n instructions (default 5)
+//   break ADDR      set a breakpoint
+//   delete ADDR     remove a breakpoint
+//   breaks          list breakpoints
+//   continue        run until a breakpoint is hit
+pub fn handle_command(emu: &mut Emulator, cmd: &str) -> String {
+    let mut words = cmd.split_whitespace();
+    let name = match words.next() {
+        Some(name) => name,
+        None => return String::new()
+    };
+    let args: Vec<&str> = words.collect();
+
+    match (name, args.as_slice()) {
+        ("step" | "s", [] | [_]) => {
+            let count = match args.first().map(|n| n.parse::<usize>()) {
+                None => 1,
+                Some(Ok(count)) => count,
+                Some(Err(_)) => return format!("bad step count: {}", args[0])
+            };
+            for _ in 0..count {
+                if let Err(err) = emu.tick() {
+                    return format!("error: {}", err);
+                }
+            }
+            current_instruction(emu)
+        },
+        ("regs" | "r", []) => registers(emu),
+        ("mem" | "m", [addr] | [addr, _]) => {
+            let addr = match parse_hex(addr) {
+                Some(addr) if (addr as usize) < RAM_SIZE => addr as usize,
+                _ => return format!("bad address: {}", addr)
+            };
+            let len = match args.get(1).map(|len| parse_hex(len)) {
+                None => MEM_BYTES_PER_LINE,
+                Some(Some(len)) => len as usize,
+                Some(None) => return format!("bad length: {}", args[1])
+            };
+            dump(emu, addr, len)
+        },
+        ("dis" | "d", [] | [_]) => {
+            let count = match args.first().map(|n| n.parse::<usize>()) {
+                None => 5,
+                Some(Ok(count)) => count,
+                Some(Err(_)) => return format!("bad count: {}", args[0])
+            };
+            let lines: Vec<String> = emu.upcoming(count)
+                .into_iter()
+                .map(|(addr, mnemonic)| format!("0x{:03X}: {}", addr, mnemonic))
+                .collect();
+            lines.join("\n")
+        },
+        ("break" | "b", [addr]) => match parse_hex(addr) {
+            Some(addr) => {
+                emu.add_breakpoint(addr);
+                format!("breakpoint set at 0x{:03X}", addr)
+            },
+            None => format!("bad address: {}", addr)
+        },
+        ("delete", [addr]) => match parse_hex(addr) {
+            Some(addr) if emu.remove_breakpoint(addr) => format!("breakpoint removed at 0x{:03X}", addr),
+            Some(addr) => format!("no breakpoint at 0x{:03X}", addr),
+            None => format!("bad address: {}", addr)
+        },
+        ("breaks", []) => {
+            let addrs: Vec<String> = emu.breakpoints().map(|addr| format!("0x{:03X}", addr)).collect();
+            if addrs.is_empty() {
+                "no breakpoints".to_string()
+            } else {
+                addrs.join(" ")
+            }
+        },
+        ("continue" | "c", []) => match emu.run_until_breakpoint(CONTINUE_MAX_CYCLES) {
+            Ok(true) => format!("hit breakpoint at 0x{:03X}\n{}", emu.pc, current_instruction(emu)),
+            Ok(false) => format!("no breakpoint hit after {} cycles\n{}", CONTINUE_MAX_CYCLES, current_instruction(emu)),
+            Err(err) => format!("error: {}", err)
+        },
+        _ => format!("unknown command: {}", cmd.trim())
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u16> {
+    u16::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+}
+
+fn current_instruction(emu: &Emulator) -> String {
+    format!("0x{:03X}: {}", emu.pc, disassemble(emu.peek_opcode()))
+}
+
+fn registers(emu: &Emulator) -> String {
+    let mut out = format!(
+        "PC=0x{:03X} I=0x{:03X} SP={} DT={} ST={}\n",
+        emu.pc, emu.i_register, emu.stack_ptr, emu.delay_t, emu.sound_t
+    );
+    let regs: Vec<String> = (0..NUM_REGISTERS).map(|x| format!("V{:X}={:02X}", x, emu.v_registers[x])).collect();
+    out.push_str(&regs.join(" "));
+    out
+}
+
+fn dump(emu: &Emulator, addr: usize, len: usize) -> String {
+    let end = (addr + len).min(RAM_SIZE);
+    let mut out = String::new();
+    for (i, line) in emu.ram[addr..end].chunks(MEM_BYTES_PER_LINE).enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let _ = write!(out, "0x{:03X}:", addr + i * MEM_BYTES_PER_LINE);
+        for byte in line {
+            let _ = write!(out, " {:02X}", byte);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emu_with(rom: &[u8]) -> Emulator {
+        Emulator::from_rom(rom).expect("test rom should load")
+    }
+
+    // LD V0, 1; ADD V0, 1 repeated
+    const COUNT_ROM: [u8; 24] = [
+        0x60, 0x01, 0x70, 0x01, 0x70, 0x01, 0x70, 0x01, 0x70, 0x01, 0x70, 0x01,
+        0x70, 0x01, 0x70, 0x01, 0x70, 0x01, 0x70, 0x01, 0x70, 0x01, 0x70, 0x01
+    ];
+
+    #[test]
+    fn step_count_is_decimal() {
+        let mut emu = emu_with(&COUNT_ROM);
+        assert_eq!(handle_command(&mut emu, "step 10"), format!("0x214: {}", disassemble(0x7001)));
+        assert_eq!(emu.v_registers[0], 10);
+        assert_eq!(handle_command(&mut emu, "step x"), "bad step count: x");
+    }
+
+    #[test]
+    fn mem_args_are_hex() {
+        let mut emu = emu_with(&COUNT_ROM);
+        assert_eq!(handle_command(&mut emu, "mem 200 4"), "0x200: 60 01 70 01");
+        assert_eq!(handle_command(&mut emu, "mem 0x200 2"), "0x200: 60 01");
+        assert_eq!(handle_command(&mut emu, "mem 1000"), "bad address: 1000");
+    }
+
+    #[test]
+    fn breakpoints_and_continue() {
+        let mut emu = emu_with(&COUNT_ROM);
+        assert_eq!(handle_command(&mut emu, "breaks"), "no breakpoints");
+        assert_eq!(handle_command(&mut emu, "break 206"), "breakpoint set at 0x206");
+        assert_eq!(handle_command(&mut emu, "breaks"), "0x206");
+        assert!(handle_command(&mut emu, "continue").starts_with("hit breakpoint at 0x206"));
+        assert_eq!(handle_command(&mut emu, "delete 206"), "breakpoint removed at 0x206");
+        assert_eq!(handle_command(&mut emu, "delete 206"), "no breakpoint at 0x206");
+    }
+
+    #[test]
+    fn dis_and_unknown_commands() {
+        let mut emu = emu_with(&COUNT_ROM);
+        let listing = handle_command(&mut emu, "dis 2");
+        assert_eq!(listing, format!("0x200: {}\n0x202: {}", disassemble(0x6001), disassemble(0x7001)));
+        assert!(handle_command(&mut emu, "regs").starts_with("PC=0x200 I=0x000 SP=0"));
+        assert_eq!(handle_command(&mut emu, "frobnicate 1"), "unknown command: frobnicate 1");
+        assert_eq!(handle_command(&mut emu, "   "), "");
+    }
+}
@@ -0,0 +1,46 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    EmptyRom, // load was given no bytes
+    BudgetExceeded, // ran out of cycles, either the max total budget or a per call limit
+    StackUnderflow, // RET with nothing on the stack
+    DisplaySizeMismatch, // a display buffer was not SCREEN_WIDTH * SCREEN_HEIGHT pixels
+    UnknownOpcode(u16), // opcode the emulator does not implement
+    InputTimeout, // FX0A waited longer than the key wait timeout
+    MemoryOutOfBounds, // an instruction tried to touch ram past RAM_SIZE
+    BadEncoding, // text encoded rom could not be decoded
+    PcOutOfBounds, // a jump, call or skip would put pc where a whole opcode no longer fits in ram, or pc ran off the end
+    RomTooLarge, // rom does not fit between START_ADDR and the end of ram
+    BadQuirkProfile, // quirk profile string had an unknown key or a value that isn't true/false
+    ProtectedRegion, // a store would have overwritten the font while font protection is on
+    StuckLoop, // the same machine state kept coming back, the program can never get anywhere
+    OpcodeNotSupportedOnPlatform(u16), // SCHIP or XO-CHIP opcode, valid elsewhere but not on plain CHIP-8
+    InvalidScale, // render scale of 0 (no output pixels) or so large the output size overflows
+    StackOverflow, // CALL with every stack slot already in use
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::EmptyRom => write!(f, "rom is empty"),
+            Chip8Error::BudgetExceeded => write!(f, "cycle budget exceeded"),
+            Chip8Error::StackUnderflow => write!(f, "stack underflow"),
+            Chip8Error::DisplaySizeMismatch => write!(f, "display buffer has the wrong number of pixels"),
+            Chip8Error::UnknownOpcode(op) => write!(f, "unknown opcode 0x{:04X}", op),
+            Chip8Error::InputTimeout => write!(f, "timed out waiting for a key press"),
+            Chip8Error::MemoryOutOfBounds => write!(f, "memory access out of bounds"),
+            Chip8Error::BadEncoding => write!(f, "rom text is not valid base64"),
+            Chip8Error::PcOutOfBounds => write!(f, "program counter out of bounds"),
+            Chip8Error::RomTooLarge => write!(f, "rom is too large to fit in ram"),
+            Chip8Error::BadQuirkProfile => write!(f, "invalid quirk profile"),
+            Chip8Error::ProtectedRegion => write!(f, "write to protected font memory"),
+            Chip8Error::StuckLoop => write!(f, "program is stuck in a loop"),
+            Chip8Error::OpcodeNotSupportedOnPlatform(op) => write!(f, "opcode 0x{:04X} is SCHIP/XO-CHIP only and not supported on CHIP-8", op),
+            Chip8Error::InvalidScale => write!(f, "render scale must be at least 1 and small enough for the output size to fit"),
+            Chip8Error::StackOverflow => write!(f, "stack overflow"),
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
@@ -0,0 +1,190 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::{Chip8Error, Emulator};
+
+const FRAME_HZ: u32 = 60;
+
+// what an EmulatorHandle can ask of its thread
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmulatorCommand {
+    Load(Vec<u8>), // reset and load a rom, stays paused
+    Keypress { key: usize, pressed: bool },
+    Run, // run frames at 60Hz until paused
+    Pause,
+    Step(usize), // run this many instructions now, running or not
+    QueryDisplay, // reply with the current display straight away
+}
+
+// what the thread sends back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmulatorResponse {
+    Display(Vec<bool>), // a QueryDisplay reply, or a frame that changed the screen while running
+    Error(Chip8Error), // a load or instruction failed, the emulator is paused
+}
+
+// an Emulator running on its own thread so a gui doesn't have to run the cpu on its ui thread
+// the emulator lives on that thread and is only reached through commands
+pub struct EmulatorHandle {
+    commands: Sender<EmulatorCommand>,
+    responses: Receiver<EmulatorResponse>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl EmulatorHandle {
+    pub fn spawn() -> Self {
+        let (commands, command_rx) = mpsc::channel();
+        let (response_tx, responses) = mpsc::channel();
+        let thread = thread::spawn(move || run(command_rx, response_tx));
+        Self { commands, responses, thread: Some(thread) }
+    }
+
+    // false if the thread has gone
+    pub fn send(&self, command: EmulatorCommand) -> bool {
+        self.commands.send(command).is_ok()
+    }
+
+    // block for the next response, None if the thread has gone
+    pub fn recv(&self) -> Option<EmulatorResponse> {
+        self.responses.recv().ok()
+    }
+
+    pub fn try_recv(&self) -> Option<EmulatorResponse> {
+        self.responses.try_recv().ok()
+    }
+}
+
+impl Drop for EmulatorHandle {
+    fn drop(&mut self) {
+        // swap in a dead sender so ours drops, the thread sees the channel close and returns
+        let (dead, _) = mpsc::channel();
+        drop(std::mem::replace(&mut self.commands, dead));
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(commands: Receiver<EmulatorCommand>, responses: Sender<EmulatorResponse>) {
+    let mut emu = Emulator::new();
+    let mut running = false;
+    let mut frame_start = Instant::now();
+    loop {
+        let command = if running {
+            let wait = emu.frame_budget(FRAME_HZ, frame_start.elapsed());
+            match commands.recv_timeout(wait) {
+                Ok(command) => Some(command),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return
+            }
+        } else {
+            match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => return
+            }
+        };
+
+        let result = match command {
+            Some(EmulatorCommand::Load(rom)) => {
+                running = false;
+                emu.reset();
+                emu.load(&rom)
+            },
+            Some(EmulatorCommand::Keypress { key, pressed }) => {
+                emu.keypress(key, pressed);
+                Ok(())
+            },
+            Some(EmulatorCommand::Run) => {
+                running = true;
+                frame_start = Instant::now();
+                Ok(())
+            },
+            Some(EmulatorCommand::Pause) => {
+                running = false;
+                Ok(())
+            },
+            Some(EmulatorCommand::Step(n)) => (0..n).try_for_each(|_| emu.tick()),
+            Some(EmulatorCommand::QueryDisplay) => {
+                let _ = responses.send(EmulatorResponse::Display(emu.display_snapshot()));
+                Ok(())
+            },
+            None => emu.step_frame().map(|_| {
+                frame_start = Instant::now();
+                // dirty rather than display_changed so a Step's draw while paused is sent with the next frame too
+                if emu.is_display_dirty() {
+                    emu.clear_display_dirty();
+                    let _ = responses.send(EmulatorResponse::Display(emu.display_snapshot()));
+                }
+            })
+        };
+        if let Err(err) = result {
+            running = false;
+            let _ = responses.send(EmulatorResponse::Error(err));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    // LD I, 0x000; DRW V0, V0, 5; JP 0x204
+    const DRAW_ONCE_ROM: [u8; 6] = [0xA0, 0x00, 0xD0, 0x05, 0x12, 0x04];
+
+    // poll instead of recv so a broken thread fails the test rather than hanging it
+    fn next_response(handle: &EmulatorHandle) -> EmulatorResponse {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(response) = handle.try_recv() {
+                return response;
+            }
+            assert!(Instant::now() < deadline, "no response from the emulator thread");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn lit(display: &[bool]) -> usize {
+        display.iter().filter(|&&on| on).count()
+    }
+
+    #[test]
+    fn running_sends_the_changed_display() {
+        let handle = EmulatorHandle::spawn();
+        assert!(handle.send(EmulatorCommand::Load(DRAW_ONCE_ROM.to_vec())));
+        assert!(handle.send(EmulatorCommand::Run));
+        match next_response(&handle) {
+            EmulatorResponse::Display(display) => {
+                assert_eq!(lit(&display), 14);
+                assert!(display[0] && display[SCREEN_WIDTH]);
+            },
+            other => panic!("expected a display, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn stepping_while_paused_then_querying() {
+        let handle = EmulatorHandle::spawn();
+        handle.send(EmulatorCommand::Load(DRAW_ONCE_ROM.to_vec()));
+        handle.send(EmulatorCommand::Step(1));
+        handle.send(EmulatorCommand::QueryDisplay);
+        assert_eq!(next_response(&handle), EmulatorResponse::Display(vec![false; SCREEN_WIDTH * SCREEN_HEIGHT]));
+        handle.send(EmulatorCommand::Step(1));
+        handle.send(EmulatorCommand::QueryDisplay);
+        match next_response(&handle) {
+            EmulatorResponse::Display(display) => assert_eq!(lit(&display), 14),
+            other => panic!("expected a display, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn a_bad_rom_reports_an_error() {
+        let handle = EmulatorHandle::spawn();
+        handle.send(EmulatorCommand::Load(Vec::new()));
+        assert_eq!(next_response(&handle), EmulatorResponse::Error(Chip8Error::EmptyRom));
+    }
+}
@@ -1,4 +1,28 @@
-use rand::random;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+mod analysis;
+mod bench;
+#[cfg(feature = "base64")]
+mod base64;
+pub mod debug;
+mod error;
+mod handle;
+mod opcode;
+#[cfg(feature = "test-utils")]
+mod test_utils;
+pub use analysis::{build_cfg, estimate_code_end, BasicBlock, Cfg};
+pub use bench::{bench_rom, BenchResult};
+pub use error::Chip8Error;
+pub use handle::{EmulatorCommand, EmulatorHandle, EmulatorResponse};
+pub use opcode::{disassemble, instructions, opcode_name, Opcode};
+#[cfg(feature = "test-utils")]
+pub use test_utils::assert_display_eq;
 
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
@@ -8,8 +32,25 @@ const NUM_REGISTERS: usize = 16;
 const STACK_SIZE: usize = 16;
 const NUM_KEYS: usize = 16;
 
+// physical layout of the original hex keypad, row by row
+pub const KEYPAD_LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF]
+];
+
+pub const DEFAULT_CPU_PER_TIMER: u32 = 9; // ~540Hz cpu against the 60Hz timers
+
+const FLICKER_FRAMES: u32 = 4; // alternating frames in a row before flicker_detected trips
+const TRACE_FLUSH_LINES: u64 = 4096; // flush the trace file this often so a crash doesn't lose the whole run
+const STUCK_WINDOW: usize = 1 << 16; // distinct states remembered by stuck loop detection before starting over
+
+const TIMER_HZ: u64 = 60; // delay and sound timers count down at 60Hz
 const START_ADDR: u16 = 0x200; // chip8 convention starts programs at 0x200, chip8 program takes up the first part of ram
 
+const FONT_ADDR: usize = 0x000; // small font lives at the very start of ram, 0x000-0x04F
+const GLYPH_SIZE: usize = 5; // each small glyph is 5 rows
 const FONTSET_SIZE: usize = 80;
 const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -36,6 +77,194 @@ const FONTSET: [u8; FONTSET_SIZE] = [
 // 00100000 = 0x20
 // 01110000 = 0x70
 
+pub const PACKED_DISPLAY_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT / 8; // 8 pixels per byte
+
+// one piece of machine state modified by an instruction, old/new values where it makes sense
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateChange {
+    Register { index: u8, old: u8, new: u8 },
+    IRegister { old: u16, new: u16 },
+    Memory { addr: u16, old: u8, new: u8 },
+    Pixel { x: usize, y: usize, on: bool },
+    DelayTimer { old: u8, new: u8 },
+    SoundTimer { old: u8, new: u8 },
+}
+
+// what a single explained instruction did, used by teaching frontends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepReport {
+    pub pc: u16, // address the opcode was fetched from
+    pub opcode: u16,
+    pub mnemonic: String,
+    pub next_pc: u16,
+    pub changes: Vec<StateChange>,
+}
+
+// everything one tick changed, kept by the delta recorder for rewinding or replaying execution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateDelta {
+    pub pc: u16,
+    pub opcode: u16,
+    pub next_pc: u16,
+    pub changes: Vec<StateChange>,
+}
+
+// the instruction at pc broken down the way tick fetches and decodes it, for showing the decode step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineView {
+    pub pc: u16,
+    pub bytes: [u8; 2], // high byte first
+    pub opcode: u16,
+    pub d1: u8, // nibbles from most to least significant, as named in execute
+    pub d2: u8,
+    pub d3: u8,
+    pub d4: u8,
+    pub decoded: Option<Opcode>, // None if execute doesn't implement it
+}
+
+// copy of the state StateChange covers, diffed after an instruction runs
+struct ChangeBase {
+    v_registers: [u8; NUM_REGISTERS],
+    i_register: u16,
+    ram: [u8; RAM_SIZE],
+    display: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+    delay_t: u8,
+    sound_t: u8,
+}
+
+// how DXYN combines sprite pixels with the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawMode {
+    Xor, // standard chip8, drawing over a lit pixel turns it off
+    Or, // never erases, handy for debug overlays, VF still reports overlap
+}
+
+// how the render helpers lay the screen out, the display buffer itself is never transformed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Normal,
+    FlipH, // mirrored left to right
+    FlipV, // mirrored top to bottom
+    Rotate180,
+}
+
+// what reset_options carries over, the default keeps nothing and is the same as reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResetOptions {
+    pub keep_display: bool, // leave the last frame on screen
+    pub keep_rom: bool, // program bytes as they are now, including anything the program wrote over itself
+    pub keep_keys: bool, // keys currently held stay held
+}
+
+// byte order for reading 16 bit data tables out of ram, opcodes themselves are always big endian
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Big,
+    Little,
+}
+
+// fixed screens for calibrating a frontend's scaling, aspect ratio and colours without a rom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestPattern {
+    Checkerboard, // (0, 0) lit, then alternating in both directions
+    Border, // outermost row and column on every edge
+    AllOn,
+    AllOff,
+}
+
+// what a ram address is used for, for colour coding memory views
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemRegion {
+    Font, // built in hex glyphs
+    Program, // bytes of the loaded rom
+    Free, // everything else, scratch space for the program
+}
+
+// behaviours that differ between chip8 interpreters, defaults match the original behaviour of this emulator except mask_i
+// mask_i defaults on, so FX1E now wraps I at 0xFFF like the hardware where it used to carry into 16 bits, turn it off for the old behaviour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    pub wrap_x: bool, // sprites crossing the right edge wrap to the left, otherwise they are clipped
+    pub wrap_y: bool, // sprites crossing the bottom edge wrap to the top, otherwise they are clipped
+    pub shift_uses_vy: bool, // COSMAC 8XY6/8XYE shift VY into VX, otherwise VX is shifted in place as on CHIP-48/SCHIP
+    pub i_wraps_on_sprite_read: bool, // DXYN sprite rows past the end of ram come from the start of ram, otherwise the draw errors
+    pub mask_i: bool, // I is 12 bits as on the original hardware, FX1E past 0xFFF wraps back to 0x000 instead of carrying into 16 bits
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            wrap_x: true,
+            wrap_y: true,
+            shift_uses_vy: false,
+            i_wraps_on_sprite_read: false,
+            mask_i: true
+        }
+    }
+}
+
+impl Quirks {
+    // compact shareable form, e.g. "wrap_x=true,wrap_y=true,shift_uses_vy=false,i_wraps_on_sprite_read=false,mask_i=true"
+    pub fn to_profile_string(&self) -> String {
+        format!("wrap_x={},wrap_y={},shift_uses_vy={},i_wraps_on_sprite_read={},mask_i={}",
+            self.wrap_x, self.wrap_y, self.shift_uses_vy, self.i_wraps_on_sprite_read, self.mask_i)
+    }
+
+    // parse to_profile_string output, keys left out keep their default and whitespace around entries is ignored
+    pub fn from_profile_string(s: &str) -> Result<Quirks, Chip8Error> {
+        let mut quirks = Quirks::default();
+        for entry in s.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (key, value) = entry.split_once('=').ok_or(Chip8Error::BadQuirkProfile)?;
+            let value = match value.trim() {
+                "true" => true,
+                "false" => false,
+                _ => return Err(Chip8Error::BadQuirkProfile)
+            };
+            match key.trim() {
+                "wrap_x" => quirks.wrap_x = value,
+                "wrap_y" => quirks.wrap_y = value,
+                "shift_uses_vy" => quirks.shift_uses_vy = value,
+                "i_wraps_on_sprite_read" => quirks.i_wraps_on_sprite_read = value,
+                "mask_i" => quirks.mask_i = value,
+                _ => return Err(Chip8Error::BadQuirkProfile)
+            }
+        }
+        Ok(quirks)
+    }
+}
+
+// what run_to_next_event stopped for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Draw, // the display changed
+    BeepStarted, // the sound timer just reached 0
+    AwaitingKey, // an FX0A is blocked waiting for input
+}
+
+// what one step_frame did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInfo {
+    pub instructions: u64, // instructions executed, less than cycles_per_frame only if the emulator is halted
+    pub beep_started: bool,
+    pub display_changed: bool,
+}
+
+// result of one tick_timers call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerTick {
+    pub beep_started: bool, // the sound timer reached 0 on this tick
+    pub delay_t: u8,
+    pub sound_t: u8,
+}
+
+// instrumentation callback, gets the emulator and the raw opcode about to run, Send like the other callbacks
+pub type OpcodeHook = Box<dyn FnMut(&Emulator, u16) + Send>;
+
+struct TraceWriter {
+    out: BufWriter<File>,
+    lines: u64,
+}
+
 pub struct Emulator {
     pc: u16, // special register program counter, keep track of idx of current instruction
     ram: [u8; RAM_SIZE], // create ram which is 4096 bytes
@@ -46,8 +275,54 @@ pub struct Emulator {
     stack: [u16; STACK_SIZE], // stack implemented as a static array
     keys: [bool; NUM_KEYS],
     delay_t: u8, // delay timer, performs action when 0, counts down every cycle
-    sound_t: u8 // chip8 emits a sound when 0, counts down every cycle
+    sound_t: u8, // chip8 emits a sound when 0, counts down every cycle
+    rom_len: usize, // number of program bytes loaded at START_ADDR
+    total_cycles: u64, // instructions executed since the last reset
+    max_total_cycles: Option<u64>, // safety net for untrusted roms, tick errors once this many cycles have run
+    halt_on_stack_error: bool, // halt and record the error instead of returning it from tick
+    halted: bool, // set when the machine stopped itself, tick does nothing until reset
+    last_error: Option<Chip8Error>, // error that caused the halt
+    quirks: Quirks,
+    display_dirty: bool, // set whenever an instruction changes a pixel, cleared by the frontend
+    count_opcodes: bool, // off by default so the hot path skips the histogram
+    opcode_counts: [u64; 16], // executions per opcode class (high nibble)
+    lenient_unknown_opcodes: bool, // skip unknown opcodes instead of erroring
+    frame: u64, // number of tick_timers calls (60Hz frames) since the last reset
+    input_schedule: VecDeque<(u64, usize, bool)>, // scripted (frame, key, pressed) events, sorted by frame
+    draw_mode: DrawMode,
+    key_wait_timeout: Option<u64>, // frames FX0A may block before erroring, for headless runs with no input
+    key_wait_start: Option<u64>, // frame the pending FX0A started waiting on
+    audio_callback: Option<Box<dyn FnMut(bool) + Send>>, // called every tick_timers with whether the buzzer should be on
+    orientation: Orientation, // presentation only, applied by the render helpers
+    max_draws_per_frame: Option<u32>, // draws past this in one frame wait for the next one
+    draws_this_frame: u32,
+    trace: Option<TraceWriter>, // execution trace being written to a file
+    signed_bcd: bool, // FX33 treats VX as an i8 and writes a sign byte before the digits
+    count_wrap_events: bool, // diagnostic for roms that assume clipping
+    wrap_events: u64, // sprite pixels DXYN wrapped across a screen edge
+    breakpoints: BTreeSet<u16>, // addresses run_until_breakpoint stops at
+    frame_hashes: [u64; 2], // display hash at the last two frame boundaries, newest first
+    flicker_frames: u32, // consecutive frames alternating between the same two screens
+    opcode_hooks: [Option<OpcodeHook>; 16], // per opcode class callbacks run just before execution
+    ticks_since_timer: u32, // cpu ticks since tick_scaled last ran the timers
+    record_collisions: bool, // debug flag for last_collision_map
+    collision_map: Vec<(usize, usize)>, // (x, y) of every lit pixel the most recent DXYN drew over
+    record_deltas: bool, // debug flag for take_deltas
+    deltas: Vec<StateDelta>, // per tick changes waiting for take_deltas
+    changed_registers: u16, // mask of V registers the last tick changed
+    rng: StdRng, // source for CXNN
+    input_provider: Option<Box<dyn FnMut(usize) -> bool + Send>>, // asked about each key the program reads, on top of keys
+    protect_font: bool, // FX55 errors instead of writing over the font
+    stuck_loop_limit: Option<u32>, // times the same state may come round before StuckLoop
+    stuck_states: HashMap<u64, u32>, // times each recent state_hash has been seen
+    cycles_per_frame: u32 // instructions per step_frame
+}
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
+
 impl Emulator {
     // init operations
     pub fn new() -> Self {
@@ -61,13 +336,60 @@ impl Emulator {
             stack: [0; STACK_SIZE],
             keys: [false; NUM_KEYS],
             delay_t: 0,
-            sound_t: 0
+            sound_t: 0,
+            rom_len: 0,
+            total_cycles: 0,
+            max_total_cycles: None,
+            halt_on_stack_error: false,
+            halted: false,
+            last_error: None,
+            quirks: Quirks::default(),
+            display_dirty: false,
+            count_opcodes: false,
+            opcode_counts: [0; 16],
+            lenient_unknown_opcodes: false,
+            frame: 0,
+            input_schedule: VecDeque::new(),
+            draw_mode: DrawMode::Xor,
+            key_wait_timeout: None,
+            key_wait_start: None,
+            audio_callback: None,
+            orientation: Orientation::Normal,
+            max_draws_per_frame: None,
+            draws_this_frame: 0,
+            trace: None,
+            signed_bcd: false,
+            count_wrap_events: false,
+            wrap_events: 0,
+            breakpoints: BTreeSet::new(),
+            frame_hashes: [0; 2],
+            flicker_frames: 0,
+            opcode_hooks: std::array::from_fn(|_| None),
+            ticks_since_timer: 0,
+            record_collisions: false,
+            collision_map: Vec::new(),
+            record_deltas: false,
+            deltas: Vec::new(),
+            changed_registers: 0,
+            rng: StdRng::from_entropy(),
+            input_provider: None,
+            protect_font: false,
+            stuck_loop_limit: None,
+            stuck_states: HashMap::new(),
+            cycles_per_frame: DEFAULT_CPU_PER_TIMER
         };
-        new_emulator.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET); // load the fontsize into ram by replacing idx 0 up to FONTSET_SIZE as FONTSET
+        new_emulator.ram[FONT_ADDR..FONT_ADDR + FONTSET_SIZE].copy_from_slice(&FONTSET); // load the fontsize into ram by replacing idx FONT_ADDR up to FONTSET_SIZE as FONTSET
 
         new_emulator
     }
 
+    // new() then load() in one step
+    pub fn from_rom(data: &[u8]) -> Result<Self, Chip8Error> {
+        let mut new_emulator = Self::new();
+        new_emulator.load(data)?;
+        Ok(new_emulator)
+    }
+
     pub fn reset(&mut self) {
         self.pc = START_ADDR;
         self.ram = [0; RAM_SIZE];
@@ -79,32 +401,456 @@ impl Emulator {
         self.keys = [false; NUM_KEYS];
         self.delay_t = 0;
         self.sound_t = 0;
-        self.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        self.rom_len = 0;
+        self.total_cycles = 0;
+        self.halted = false;
+        self.last_error = None;
+        self.display_dirty = false;
+        self.opcode_counts = [0; 16];
+        self.frame = 0;
+        self.input_schedule = VecDeque::new();
+        self.key_wait_start = None;
+        self.draws_this_frame = 0;
+        self.wrap_events = 0;
+        self.frame_hashes = [0; 2];
+        self.flicker_frames = 0;
+        self.ticks_since_timer = 0;
+        self.collision_map = Vec::new();
+        self.deltas = Vec::new();
+        self.changed_registers = 0;
+        self.stuck_states = HashMap::new();
+        self.ram[FONT_ADDR..FONT_ADDR + FONTSET_SIZE].copy_from_slice(&FONTSET);
+    }
+
+    // reset with some state carried over, for warm reboots while debugging
+    pub fn reset_options(&mut self, options: ResetOptions) {
+        let display = self.display;
+        let display_dirty = self.display_dirty;
+        let keys = self.keys;
+        let rom_len = self.rom_len;
+        let start = START_ADDR as usize;
+        let rom = self.ram[start..start + rom_len].to_vec();
+
+        self.reset();
+
+        if options.keep_display {
+            self.display = display;
+            self.display_dirty = display_dirty;
+        }
+        if options.keep_rom {
+            self.ram[start..start + rom_len].copy_from_slice(&rom);
+            self.rom_len = rom_len;
+        }
+        if options.keep_keys {
+            self.keys = keys;
+        }
     }
 
     // CPU operations
-    pub fn tick(&mut self) {
+    pub fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.changed_registers = 0; // a tick that runs nothing changes nothing
+        if self.halted {
+            return Ok(());
+        }
+        self.check_pc(self.pc)?; // only falling through the last instruction in ram can get here
+        if let Some(max) = self.max_total_cycles {
+            if self.total_cycles >= max {
+                return Err(Chip8Error::BudgetExceeded);
+            }
+        }
         // basic tick process
         //1. fetch value from the game that has already been loaded into ram at the program counter
         //2. decode the instruction
         //3. execute, may involve editing the registers or stack
         //4. move pc to the next instruction, repeat
+        let pc = self.pc;
+        let base = self.record_deltas.then(|| self.change_base());
+        let v_before = self.v_registers;
         let opcode = self.fetch();
-        self.execute(opcode);
+        self.write_trace(pc, opcode);
+        if !self.draw_deferred(opcode) { // a held back draw runs its hook when it actually executes
+            self.run_opcode_hook(opcode);
+        }
+        let result = self.execute(opcode);
+        self.changed_registers = v_before.iter().zip(self.v_registers.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .fold(0, |mask, (idx, _)| mask | (1 << idx));
+        if let Err(err) = result {
+            self.pc -= 2; // leave pc on the faulting instruction
+            if matches!(err, Chip8Error::StackUnderflow | Chip8Error::StackOverflow) && self.halt_on_stack_error {
+                self.halted = true;
+                self.last_error = Some(err);
+                return Ok(());
+            }
+            return Err(err);
+        }
+        self.check_invariants();
+        self.total_cycles += 1;
+        if self.count_opcodes {
+            self.opcode_counts[(opcode >> 12) as usize] += 1;
+        }
+        if let Some(base) = base {
+            let changes = self.changes_since(&base);
+            self.deltas.push(StateDelta { pc, opcode, next_pc: self.pc, changes });
+        }
+        if let (Some(limit), None) = (self.stuck_loop_limit, self.key_wait_start) {
+            // seen counts are only kept for a window so a long running program doesn't grow the map forever
+            if self.stuck_states.len() >= STUCK_WINDOW {
+                self.stuck_states.clear();
+            }
+            let seen = self.stuck_states.entry(self.state_hash()).or_insert(0);
+            *seen += 1;
+            if *seen > limit {
+                return Err(Chip8Error::StuckLoop);
+            }
+        }
+        Ok(())
     }
 
-    fn fetch(&mut self) -> u16 {
+    // tick never touches the timers, they run at 60Hz independent of the cpu speed
+    // the canonical loop is a fixed number of ticks (the desktop frontend uses 10) and then one tick_timers per 60Hz frame
+    // this does one tick and, when decrement_timers is set, the frame's tick_timers straight after
+    // so a frontend can pass true on the last tick of each frame instead of interleaving the two calls itself
+    pub fn tick_with_timers(&mut self, decrement_timers: bool) -> Result<(), Chip8Error> {
+        self.tick()?;
+        if decrement_timers {
+            self.tick_timers();
+        }
+        Ok(())
+    }
+
+    // one tick, with the timers run after every cpu_per_timer ticks so a frontend can just call this in a loop
+    pub fn tick_scaled(&mut self, cpu_per_timer: u32) -> Result<(), Chip8Error> {
+        self.tick_scaled_timers(cpu_per_timer)?;
+        Ok(())
+    }
+
+    // tick_scaled, also handing back the timer result on the ticks that ran the timers
+    fn tick_scaled_timers(&mut self, cpu_per_timer: u32) -> Result<Option<TimerTick>, Chip8Error> {
+        self.tick()?;
+        self.ticks_since_timer += 1;
+        if self.ticks_since_timer >= cpu_per_timer {
+            self.ticks_since_timer = 0;
+            return Ok(Some(self.tick_timers()));
+        }
+        Ok(None)
+    }
+
+    // one 60Hz frame: cycles_per_frame instructions then a tick_timers, the simplest way to drive the emulator
+    // display_changed only covers this frame, is_display_dirty is left set if it already was
+    pub fn step_frame(&mut self) -> Result<FrameInfo, Chip8Error> {
+        let start_cycles = self.total_cycles;
+        let was_dirty = self.display_dirty;
+        self.display_dirty = false;
+
+        let result = (0..self.cycles_per_frame).try_for_each(|_| self.tick());
+        let display_changed = self.display_dirty;
+        self.display_dirty |= was_dirty;
+        result?;
+
+        self.ticks_since_timer = 0;
+        let timers = self.tick_timers();
+        Ok(FrameInfo {
+            instructions: self.total_cycles - start_cycles,
+            beep_started: timers.beep_started,
+            display_changed
+        })
+    }
+
+    // instructions step_frame runs per frame, DEFAULT_CPU_PER_TIMER unless set
+    pub fn set_cycles_per_frame(&mut self, cycles: u32) {
+        self.cycles_per_frame = cycles;
+    }
+
+    // drive the cpu and timers (as tick_scaled with DEFAULT_CPU_PER_TIMER) until something a host reacts to happens
+    // errors with BudgetExceeded if nothing happens within max_cycles
+    // a redraw from before the call stays flagged for the frontend but isn't reported as an event
+    pub fn run_to_next_event(&mut self, max_cycles: usize) -> Result<Event, Chip8Error> {
+        let was_dirty = self.display_dirty;
+        self.display_dirty = false;
+        let result = self.next_event(max_cycles);
+        self.display_dirty |= was_dirty;
+        result
+    }
+
+    fn next_event(&mut self, max_cycles: usize) -> Result<Event, Chip8Error> {
+        for _ in 0..max_cycles {
+            let timers = self.tick_scaled_timers(DEFAULT_CPU_PER_TIMER)?;
+            if self.display_dirty {
+                return Ok(Event::Draw);
+            }
+            if timers.is_some_and(|timers| timers.beep_started) {
+                return Ok(Event::BeepStarted);
+            }
+            if self.is_waiting_for_key() {
+                return Ok(Event::AwaitingKey);
+            }
+        }
+        Err(Chip8Error::BudgetExceeded)
+    }
+
+    // error with StuckLoop once the exact same state (pc, registers, stack, timers, keys, ram and display) comes round more than limit times
+    // an FX0A waiting on a key is never counted, but EX9E/EXA1 polling with no key down is, and a loop polling the delay timer
+    // repeats its states until the next tick_timers, so keep limit well above the instructions run per frame
+    // CXNN loops might trip it if the numbers repeat
+    // hashes the whole machine every tick so it's for headless runs rather than normal play, None turns it off
+    pub fn set_stuck_loop_limit(&mut self, limit: Option<u32>) {
+        self.stuck_loop_limit = limit;
+        self.stuck_states.clear();
+    }
+
+    // None removes the budget
+    pub fn set_max_total_cycles(&mut self, max: Option<u64>) {
+        self.max_total_cycles = max;
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    // turning mask_i on masks an I that grew past 12 bits while it was off, the same as FX1E would have
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        if quirks.mask_i {
+            self.i_register &= 0xFFF;
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // make CXNN (and the test-utils corruption helpers) repeat the same numbers every run, the rng is seeded from the os otherwise
+    // not cleared by reset, seed again to restart the sequence
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn set_opcode_histogram_enabled(&mut self, enabled: bool) {
+        self.count_opcodes = enabled;
+    }
+
+    // executed instruction counts keyed by opcode class, classes that never ran are left out
+    pub fn opcode_histogram(&self) -> HashMap<u8, u64> {
+        self.opcode_counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(class, &count)| (class as u8, count))
+            .collect()
+    }
+
+    // true while an FX0A is blocked waiting for a key, for "press a key" prompts
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait_start.is_some()
+    }
+
+    // None waits forever like the original hardware
+    pub fn set_key_wait_timeout(&mut self, frames: Option<u64>) {
+        self.key_wait_timeout = frames;
+    }
+
+    // flicker reduction, None (the default) never defers a draw
+    pub fn set_max_draws_per_frame(&mut self, max: Option<u32>) {
+        self.max_draws_per_frame = max;
+    }
+
+    // off by default, standard FX33 is unsigned
+    pub fn set_signed_bcd(&mut self, signed: bool) {
+        self.signed_bcd = signed;
+    }
+
+    pub fn set_wrap_event_counting(&mut self, enabled: bool) {
+        self.count_wrap_events = enabled;
+    }
+
+    // number of sprite pixels wrapped around an edge since reset, only counted while enabled
+    pub fn wrap_events(&self) -> u64 {
+        self.wrap_events
+    }
+
+    pub fn set_collision_recording(&mut self, enabled: bool) {
+        self.record_collisions = enabled;
+    }
+
+    // where the latest DXYN hit already lit pixels (turning them off in XOR mode), only filled while recording is on
+    pub fn last_collision_map(&self) -> Vec<(usize, usize)> {
+        self.collision_map.clone()
+    }
+
+    // make FX55 stores that reach into the font fail with ProtectedRegion instead of corrupting the glyphs
+    pub fn set_font_protection(&mut self, enabled: bool) {
+        self.protect_font = enabled;
+    }
+
+    pub fn set_draw_mode(&mut self, mode: DrawMode) {
+        self.draw_mode = mode;
+    }
+
+    // off by default so bad control flow into data is surfaced, on treats unknown opcodes as NOPs
+    pub fn set_lenient_unknown_opcodes(&mut self, lenient: bool) {
+        self.lenient_unknown_opcodes = lenient;
+    }
+
+    // when on, a stack error (StackUnderflow from an empty RET or StackOverflow from a CALL with the stack full)
+    // halts the machine and stores the error for last_error() instead of making tick fail
+    pub fn set_halt_on_stack_error(&mut self, halt: bool) {
+        self.halt_on_stack_error = halt;
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn last_error(&self) -> Option<Chip8Error> {
+        self.last_error
+    }
+
+    // tick and hand back (pc, opcode) of the instruction it ran, for pull style tracing
+    // None once the machine is halted (including by this tick's instruction) since nothing ran
+    pub fn tick_traced(&mut self) -> Result<Option<(u16, u16)>, Chip8Error> {
+        let pc = self.pc;
+        let opcode = self.peek_opcode();
+        self.tick()?;
+        if self.halted {
+            return Ok(None);
+        }
+        Ok(Some((pc, opcode)))
+    }
+
+    // tick once and describe everything the instruction changed
+    pub fn explain_step(&mut self) -> Result<StepReport, Chip8Error> {
+        let pc = self.pc;
+        let opcode = self.peek_opcode();
+        let base = self.change_base();
+
+        self.tick()?;
+
+        Ok(StepReport {
+            pc,
+            opcode,
+            mnemonic: disassemble(opcode),
+            next_pc: self.pc,
+            changes: self.changes_since(&base)
+        })
+    }
+
+    // record a StateDelta for every successful tick until turned off, collect them with take_deltas
+    pub fn set_delta_recording(&mut self, enabled: bool) {
+        self.record_deltas = enabled;
+    }
+
+    // deltas recorded since the last call, oldest first
+    pub fn take_deltas(&mut self) -> Vec<StateDelta> {
+        std::mem::take(&mut self.deltas)
+    }
+
+    fn change_base(&self) -> ChangeBase {
+        ChangeBase {
+            v_registers: self.v_registers,
+            i_register: self.i_register,
+            ram: self.ram,
+            display: self.display,
+            delay_t: self.delay_t,
+            sound_t: self.sound_t
+        }
+    }
+
+    fn changes_since(&self, base: &ChangeBase) -> Vec<StateChange> {
+        let mut changes = Vec::new();
+        for (idx, (&old, &new)) in base.v_registers.iter().zip(self.v_registers.iter()).enumerate() {
+            if old != new {
+                changes.push(StateChange::Register { index: idx as u8, old, new });
+            }
+        }
+        if base.i_register != self.i_register {
+            changes.push(StateChange::IRegister { old: base.i_register, new: self.i_register });
+        }
+        for (addr, (&old, &new)) in base.ram.iter().zip(self.ram.iter()).enumerate() {
+            if old != new {
+                changes.push(StateChange::Memory { addr: addr as u16, old, new });
+            }
+        }
+        for (idx, (&old, &new)) in base.display.iter().zip(self.display.iter()).enumerate() {
+            if old != new {
+                let (x, y) = display_coords(idx);
+                changes.push(StateChange::Pixel { x, y, on: new });
+            }
+        }
+        if base.delay_t != self.delay_t {
+            changes.push(StateChange::DelayTimer { old: base.delay_t, new: self.delay_t });
+        }
+        if base.sound_t != self.sound_t {
+            changes.push(StateChange::SoundTimer { old: base.sound_t, new: self.sound_t });
+        }
+        changes
+    }
+
+    // read the opcode at pc without moving it, bytes past the end of ram read as 0 for the debug views
+    fn peek_opcode(&self) -> u16 {
         // chip8 opcodes are exactly 2 bytes and store the information needed inside them instead of elsewhere
-        let higher_byte = self.ram[self.pc as usize] as u16; // fetch 1 byte
-        let lower_byte = self.ram[(self.pc + 1) as usize] as u16; // fetch other byte for 16 total bits
+        let byte = |addr: usize| self.ram.get(addr).copied().unwrap_or(0) as u16;
+        let higher_byte = byte(self.pc as usize); // fetch 1 byte
+        let lower_byte = byte(self.pc as usize + 1); // fetch other byte for 16 total bits
 
         // logical shift higher byte left 8, then OR lower byte into the freed 8 bits
-        let op = (higher_byte << 8) | lower_byte; // combine both bytes into 8 bit value by big endian
+        (higher_byte << 8) | lower_byte // combine both bytes into 8 bit value by big endian
+    }
+
+    fn fetch(&mut self) -> u16 {
+        let op = self.peek_opcode();
         self.pc += 2; // increase past these most recent ram addressses
         op
     }
 
-    fn execute(&mut self, op: u16) {
+    // sanity checks on state every handler has to leave valid, compiled out of release builds
+    // pc isn't asserted to be even, jumping to an odd address is legal if unusual and some roms do it
+    // jumps, calls and skips refuse targets without room for an opcode, but the last instruction in ram can still
+    // fall through (or RET back) to just past the end, which the next tick reports as PcOutOfBounds
+    fn check_invariants(&self) {
+        debug_assert!(self.pc as usize <= RAM_SIZE, "pc 0x{:04X} past the end of ram", self.pc);
+        debug_assert!(self.stack_ptr as usize <= STACK_SIZE, "stack pointer {} past the stack", self.stack_ptr);
+        debug_assert!(!self.quirks.mask_i || self.i_register <= 0xFFF, "I 0x{:04X} wider than 12 bits", self.i_register);
+    }
+
+    // run a single opcode directly, skipping fetch so pc and ram are untouched
+    #[cfg(feature = "test-utils")]
+    pub fn execute_opcode(&mut self, op: u16) -> Result<(), Chip8Error> {
+        self.execute(op)
+    }
+
+    // fault injection, writes straight to ram with no checks, addresses past the end wrap around
+    #[cfg(feature = "test-utils")]
+    pub fn corrupt_ram(&mut self, addr: u16, value: u8) {
+        self.ram[addr as usize % RAM_SIZE] = value;
+    }
+
+    // flip one random bit in each of count random ram bytes
+    #[cfg(feature = "test-utils")]
+    pub fn corrupt_random(&mut self, count: usize) {
+        for _ in 0..count {
+            let addr = self.rng.gen_range(0..RAM_SIZE);
+            self.ram[addr] ^= 1 << self.rng.gen_range(0..8);
+        }
+    }
+
+    // true for a DXYN past max_draws_per_frame, which execute leaves for the next frame
+    fn draw_deferred(&self, op: u16) -> bool {
+        let is_draw = op >> 12 == 0xD && op & 0xF != 0;
+        is_draw && self.max_draws_per_frame.is_some_and(|max| self.draws_this_frame >= max)
+    }
+
+    // shared by every skip opcode, fetch has already moved past this instruction so one more opcode skips the next
+    fn skip_if(&mut self, condition: bool) -> Result<(), Chip8Error> {
+        if condition {
+            self.check_pc(self.pc + 2)?;
+            self.pc += 2;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, op: u16) -> Result<(), Chip8Error> {
         let d1 = (op & 0xF000) >> 12;
         let d2 = (op & 0x0F00) >> 8;
         let d3 = (op & 0x00F0) >> 4;
@@ -112,24 +858,33 @@ impl Emulator {
 
         match (d1, d2, d3, d4) {
             // NOP
-            (0,0,0,0) => return,
+            (0,0,0,0) => (),
             // CLS
             (0,0,0xE,0) => {
+                self.display_dirty |= self.display.contains(&true);
                 self.display = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
             },
             // RET
             (0,0,0xE,0xE) => { // when entering subroutine, push current address onto stack, this function then pops it back when returning
+                if self.stack_ptr == 0 {
+                    return Err(Chip8Error::StackUnderflow);
+                }
                 let return_addr = self.pop();
                 self.pc = return_addr;
             },
             // JMP 0xNNN
             (1,_,_,_) => { // set pc to the given op code address
                 let nnn = op & 0xFFF;
+                self.check_pc(nnn)?;
                 self.pc = nnn;
             },
             // CALL 0xNNN
             (2,_,_,_) => { // set pc to the given op code address, _ wildcard, catch all op start with 2
                 let nnn = op & 0xFFF;
+                if self.stack_ptr as usize == STACK_SIZE {
+                    return Err(Chip8Error::StackOverflow);
+                }
+                self.check_pc(nnn)?;
                 self.push(self.pc);
                 self.pc = nnn;
             },
@@ -137,25 +892,19 @@ impl Emulator {
             (3,_,_,_) => {
                 let x = d2 as usize;
                 let nn = (op & 0xFF) as u8;
-                if self.v_registers[x] == nn {
-                    self.pc += 2; // skip to next opcode, aka increase pc by 2 bytes
-                }
+                self.skip_if(self.v_registers[x] == nn)?;
             },
-            // SKIP VX == NN
+            // SKIP VX != NN
             (4,_,_,_) => {
                 let x = d2 as usize;
-                let y = d3 as usize;
-                if self.v_registers[x] == self.v_registers[y] {
-                    self.pc += 2; // skip to next opcode, aka increase pc by 2 bytes
-                }
+                let nn = (op & 0xFF) as u8; // & 0xFF gives last 8 bits of op
+                self.skip_if(self.v_registers[x] != nn)?;
             },
-            // SKIP VX == NN
+            // SKIP VX == VY
             (5,_,_,0) => {
                 let x = d2 as usize;
-                let nn = (op & 0xFF) as u8; // & 0xFF gives last 8 bits of op
-                if self.v_registers[x] != nn {
-                    self.pc += 2; // skip to next opcode, aka increase pc by 2 bytes
-                }
+                let y = d3 as usize;
+                self.skip_if(self.v_registers[x] == self.v_registers[y])?;
             },
             // VX = NN
             (6,_,_,_) => {
@@ -215,9 +964,10 @@ impl Emulator {
             // VX >> 1
             (8,_,_,6) => {
                 let x = d2 as usize;
-                let lsb = self.v_registers[x] & 1; //least significant bit, catch and set VF
-                self.v_registers[x] >>= 1; // right shift equal
-                self.v_registers[0xF] = lsb;
+                let src = if self.quirks.shift_uses_vy { self.v_registers[d3 as usize] } else { self.v_registers[x] };
+                let lsb = src & 1; //least significant bit, catch and set VF
+                self.v_registers[x] = src >> 1; // right shift
+                self.v_registers[0xF] = lsb; // VF last so when X is F it ends up holding the shifted out bit, not the shifted value
             },
             // VX = VY - VX
             (8,_,_,7) => {
@@ -231,17 +981,16 @@ impl Emulator {
             // VX << 1
             (8,_,_,0xE) => {
                 let x = d2 as usize;
-                let msb = (self.v_registers[x] >> 7) & 1; //most significant bit, catch and set VF
-                self.v_registers[x] <<= 1; // right shift equal
-                self.v_registers[0xF] = msb;
+                let src = if self.quirks.shift_uses_vy { self.v_registers[d3 as usize] } else { self.v_registers[x] };
+                let msb = (src >> 7) & 1; //most significant bit, catch and set VF
+                self.v_registers[x] = src << 1; // left shift
+                self.v_registers[0xF] = msb; // VF last, same as 8XY6
             },
             // SKIP VX != VY
-            (9,_,_,0) => {
+            (9,_,_,0) => { // low nibble must be 0, 9XYN for any other N is not a valid opcode
                 let x = d2 as usize;
                 let y = d3 as usize;
-                if self.v_registers[x] != self.v_registers[y] {
-                    self.pc += 2;
-                }
+                self.skip_if(self.v_registers[x] != self.v_registers[y])?;
             },
             // SET I register
             (0xA,_,_,_) => {
@@ -251,66 +1000,98 @@ impl Emulator {
             // SET pc to I register 0 value plus input
             (0xB,_,_,_) => {
                 let nnn = op & 0xFFF;
-                self.pc = (self.v_registers[0] as u16) + nnn;
+                let target = (self.v_registers[0] as u16) + nnn; // up to 0xFF + 0xFFF, past the end of ram
+                self.check_pc(target)?;
+                self.pc = target;
             },
             // VX = rand() & NN
             (0xC,_,_,_) => {
                 let x = d2 as usize;
                 let nn = (op & 0xFFF) as u8;
-                let rng:u8 = random();
+                let rng: u8 = self.rng.gen();
                 self.v_registers[x] = rng & nn;
             },
             // Draw Sprite XY
-            (0xD,_,_,_) => {
-                let x_cord = self.v_registers[d2 as usize] as u16;
-                let y_cord = self.v_registers[d3 as usize] as u16;
+            (0xD,_,_,_) if d4 != 0 => { // DXY0 is the SCHIP big sprite, left to the catch all
+                if self.draw_deferred(op) { // defer to the next frame, redo opcode
+                    self.pc -= 2;
+                    return Ok(());
+                }
+                if !self.quirks.i_wraps_on_sprite_read {
+                    self.check_ram_range(self.i_register as usize, d4 as usize)?; // before anything is drawn, same as FX55
+                }
+                self.draws_this_frame += 1;
+                if self.record_collisions {
+                    self.collision_map.clear();
+                }
+
+                // the start position always wraps onto the screen, the quirks only decide if the rest of the sprite wraps or clips
+                // coordinates are read before VF is overwritten with the collision flag below, so DXYN with X or Y as F draws where VF said
+                let x_cord = self.v_registers[d2 as usize] as usize % SCREEN_WIDTH;
+                let y_cord = self.v_registers[d3 as usize] as usize % SCREEN_HEIGHT;
                 let num_rows = d4;
                 // chip 8 sprites are always 8 pixels wide, variable pixels tall (specified in d4)
 
                 let mut flipped = false; // keep track if any pixels were flipped (black <-> white)
                 // iterate over each row of the sprite
                 for y_line in 0..num_rows {
-                    let addr = self.i_register + y_line as u16;
-                    let pixels = self.ram[addr as usize];
+                    let addr = (self.i_register as usize + y_line as usize) % RAM_SIZE; // only wraps with the quirk on, the range was checked otherwise
+                    let pixels = self.ram[addr];
                     // iterate over each column in the row
                     for x_line in 0..8 {
                         // fetch current pixels bit
                         if (pixels & (0b1000_0000 >> x_line)) != 0 { // only flip if a one
+                            let mut x = x_cord + x_line as usize;
+                            let mut y = y_cord + y_line as usize;
+                            if (x >= SCREEN_WIDTH && !self.quirks.wrap_x) || (y >= SCREEN_HEIGHT && !self.quirks.wrap_y) {
+                                continue; // clipped
+                            }
+                            if self.count_wrap_events && (x >= SCREEN_WIDTH || y >= SCREEN_HEIGHT) {
+                                self.wrap_events += 1;
+                            }
                             // wrap sprites around screen
-                            let x = (x_cord + x_line) as usize % SCREEN_WIDTH;
-                            let y = (y_cord + y_line) as usize % SCREEN_HEIGHT;
-                            
+                            x %= SCREEN_WIDTH;
+                            y %= SCREEN_HEIGHT;
+
                             // get pixels idx over the 1d screen array
-                            let idx = x + SCREEN_WIDTH * y;
+                            let idx = display_index(x, y);
                             flipped |= self.display[idx];
-                            self.display[idx] ^= true;
+                            if self.record_collisions && self.display[idx] {
+                                self.collision_map.push((x, y));
+                            }
+                            match self.draw_mode {
+                                DrawMode::Xor => {
+                                    self.display[idx] ^= true;
+                                    self.display_dirty = true;
+                                },
+                                DrawMode::Or => {
+                                    self.display_dirty |= !self.display[idx];
+                                    self.display[idx] = true;
+                                }
+                            }
                         }
                     }
                 }
                 if flipped {
-                    self.v_registers[0] = 1;
+                    self.v_registers[0xF] = 1;
                 }
                 else {
-                    self.v_registers[0] = 0;
+                    self.v_registers[0xF] = 0;
                 }
             },
             // SKIP KEY PRESS
             (0xE,_,9,0xE) => {
                 let x = d2 as usize;
-                let vx = self.v_registers[x];
-                let key = self.keys[vx as usize];
-                if key {
-                    self.pc += 2;
-                }
+                let vx = self.v_registers[x] & 0xF; // only the low nibble names a key, same as the original interpreter
+                let key = self.key_down(vx as usize);
+                self.skip_if(key)?;
             },
             // SKIP KEY RELEASE
             (0xE,_,0xA,1) => {
                 let x = d2 as usize;
-                let vx = self.v_registers[x];
-                let key = self.keys[vx as usize];
-                if !key {
-                    self.pc += 2;
-                }
+                let vx = self.v_registers[x] & 0xF;
+                let key = self.key_down(vx as usize);
+                self.skip_if(!key)?;
             },
             // VX = DT
             (0xF,_,0,7) => {
@@ -322,14 +1103,23 @@ impl Emulator {
                 let x = d2 as usize;
                 let mut pressed = false;
                 for i in 0..self.keys.len() {
-                    if self.keys[i] {
+                    if self.key_down(i) {
                         self.v_registers[x] = i as u8;
                         pressed = true;
                         break;
                     }
                 }
-                if !pressed { // redo opcode
-                    self.pc -= 2;
+                if pressed {
+                    self.key_wait_start = None;
+                } else {
+                    let start = *self.key_wait_start.get_or_insert(self.frame);
+                    if let Some(timeout) = self.key_wait_timeout {
+                        if self.frame - start >= timeout {
+                            self.key_wait_start = None;
+                            return Err(Chip8Error::InputTimeout);
+                        }
+                    }
+                    self.pc -= 2; // redo opcode
                 }
             },
             // DT = VX
@@ -346,84 +1136,2061 @@ impl Emulator {
             (0xF,_,1,0xE) => {
                 let x = d2 as usize;
                 let vx = self.v_registers[x] as u16;
-                self.i_register += self.i_register.wrapping_add(vx);
+                self.i_register = self.i_register.wrapping_add(vx);
+                if self.quirks.mask_i {
+                    self.i_register &= 0xFFF; // ANNN and FX29 can only give 12 bit values so this is the one place I can grow past it
+                }
             },
             // I = FONT
             (0xF,_,2,9) => {
                 let x = d2 as usize;
-                let c = self.v_registers[x] as u16;
-                self.i_register = c * 5;
+                let c = (self.v_registers[x] & 0xF) as usize; // only 0-F have glyphs, mask so a bad VX can't point past the font into program ram
+                self.i_register = (FONT_ADDR + c * GLYPH_SIZE) as u16;
             },
             // BCD
             (0xF,_,3,3) => {
                 let x = d2 as usize;
-                let vx = self.v_registers[x] as f32;
+                let mut i = self.i_register as usize;
+                let mut vx = self.v_registers[x];
+                if self.signed_bcd {
+                    // 4 bytes instead of 3, a sign byte (1 for negative) then the digits of the magnitude
+                    self.check_ram_range(i, 4)?;
+                    let signed = vx as i8;
+                    self.ram[i] = (signed < 0) as u8;
+                    vx = signed.unsigned_abs();
+                    i += 1;
+                }
+                let vx = vx as f32;
 
                 let hundreds = (vx / 100.0).floor() as u8;
                 let tens = ((vx / 10.0) % 10.0).floor() as u8;
                 let ones = (vx % 10.0) as u8;
 
-                self.ram[self.i_register as usize] = hundreds;
-                self.ram[(self.i_register + 1) as usize] = tens;
-                self.ram[(self.i_register + 2) as usize] = ones;
+                self.check_ram_range(i, 3)?;
+                self.ram[i] = hundreds;
+                self.ram[i + 1] = tens;
+                self.ram[i + 2] = ones;
             },
             // STORE V0-VX
             (0xF,_,5,5) => {
                 let x = d2 as usize;
                 let i = self.i_register as usize;
-                for idx in 0..=x {
-                    self.ram[i+idx] = self.v_registers[idx];
-                }
+                self.check_ram_range(i, x + 1)?; // check the whole range up front so a bad I can't leave a half finished store
+                self.check_font_write(i, x + 1)?;
+                // whole slice copies, registers and ram never alias so storing over the running code can't feed back into the copy
+                self.ram[i..=i + x].copy_from_slice(&self.v_registers[..=x]);
             },
             // LOAD V0-VX
             (0xF,_,6,5) => {
                 let x = d2 as usize;
                 let i = self.i_register as usize;
-                for idx in 0..=x {
-                    self.v_registers[idx] = self.ram[i + idx];
-                }
+                self.check_ram_range(i, x + 1)?;
+                self.v_registers[..=x].copy_from_slice(&self.ram[i..=i + x]);
             },
-            (_, _, _, _) => unimplemented!("Unimplemented opcode: {}", op) // catch all
+            (_, _, _, _) => { // catch all
+                if !self.lenient_unknown_opcodes {
+                    if opcode::is_extension_opcode(op) {
+                        return Err(Chip8Error::OpcodeNotSupportedOnPlatform(op));
+                    }
+                    return Err(Chip8Error::UnknownOpcode(op));
+                }
+                // lenient, pc has already moved past it so just carry on
+            }
         }
+        Ok(())
     }
 
-    pub fn tick_timers(&mut self) {
+    pub fn tick_timers(&mut self) -> TimerTick {
         if self.delay_t > 0 {
             self.delay_t -= 1; // count down
         }
 
+        let mut beep_started = false;
         if self.sound_t > 0 {
-            if self.sound_t == 0 {
-                //beep 
-            }
             self.sound_t -= 1; // count down
+            beep_started = self.sound_t == 0; // beep on the transition to 0, not on every frame it stays there
+        }
+
+        self.frame += 1;
+        self.draws_this_frame = 0;
+
+        // A B A B... at frame boundaries is the classic draw/erase flicker
+        let hash = fnv1a(self.display.iter().map(|&on| on as u8));
+        if hash == self.frame_hashes[1] && hash != self.frame_hashes[0] {
+            self.flicker_frames += 1;
+        } else {
+            self.flicker_frames = 0;
+        }
+        self.frame_hashes = [hash, self.frame_hashes[0]];
+        while let Some(&(frame, key, pressed)) = self.input_schedule.front() {
+            if frame > self.frame {
+                break;
+            }
+            self.keypress(key, pressed);
+            self.input_schedule.pop_front();
+        }
+
+        if let Some(callback) = self.audio_callback.as_mut() {
+            callback(self.sound_t > 0);
+        }
+
+        TimerTick {
+            beep_started,
+            delay_t: self.delay_t,
+            sound_t: self.sound_t
         }
     }
 
-    // stack operations
-    pub fn push(&mut self, val:u16) {
-        self.stack[self.stack_ptr as usize] = val; // change the current stack pointer to the val
-        self.stack_ptr += 1 // increase the stack pointer after modification
+    // push style audio, the callback runs once per frame so callback based audio libraries don't need to poll
+    // Send so the emulator can still be moved to another thread with a callback set
+    pub fn set_audio_callback(&mut self, callback: Box<dyn FnMut(bool) + Send>) {
+        self.audio_callback = Some(callback);
     }
 
-    pub fn pop(&mut self) -> u16 {
-        self.stack_ptr -= 1; // move to previous position
-        self.stack[self.stack_ptr as usize] // return the value of the stack at the pointer
+    // lets a bot or test drive the keypad, EX9E/EXA1/FX0A see a key as down if the provider or the keys array says so
+    // the provider is only ever asked about keys 0-F
+    pub fn set_input_provider(&mut self, provider: Box<dyn FnMut(usize) -> bool + Send>) {
+        self.input_provider = Some(provider);
     }
 
-    // interaction operations
-    pub fn get_display(&self) -> &[bool] {
-        &self.display
+    fn key_down(&mut self, key: usize) -> bool {
+        let provided = self.input_provider.as_mut().is_some_and(|provider| provider(key));
+        self.keys[key] || provided
     }
 
-    pub fn keypress(&mut self, idx:usize, pressed:bool) {
-        self.keys[idx] = pressed;
+    pub fn frame(&self) -> u64 {
+        self.frame
     }
 
-    // ram operations
-    pub fn load(&mut self, data: &[u8]) {
-        let start = START_ADDR as usize;
-        let end = (START_ADDR as usize) + data.len();
-        self.ram[start..end].copy_from_slice(data);
+    // script input as (frame, key, pressed), each event is applied by the tick_timers call that brings frame() up to it
+    // events for frames that have already passed are applied at the next frame boundary
+    pub fn set_input_schedule(&mut self, mut events: Vec<(u64, usize, bool)>) {
+        events.sort_by_key(|&(frame, _, _)| frame); // stable so same frame events keep their order
+        self.input_schedule = events.into();
+    }
+
+    // same machine state (cpu, memory, display, keys, timers), ignoring settings, callbacks and counters
+    // only the live part of the stack is compared, stale entries above the pointer don't matter
+    pub fn state_eq(&self, other: &Emulator) -> bool {
+        self.pc == other.pc
+            && self.v_registers == other.v_registers
+            && self.i_register == other.i_register
+            && self.call_stack() == other.call_stack()
+            && self.delay_t == other.delay_t
+            && self.sound_t == other.sound_t
+            && self.keys == other.keys
+            && self.ram == other.ram
+            && self.display == other.display
+    }
+
+    // debugger operations
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    // true if there was a breakpoint at addr
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    // tick at least once and keep going until pc lands on a breakpoint, false if max_cycles ran out first
+    pub fn run_until_breakpoint(&mut self, max_cycles: usize) -> Result<bool, Chip8Error> {
+        for _ in 0..max_cycles {
+            self.tick()?;
+            if self.breakpoints.contains(&self.pc) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // append "frame pc opcode mnemonic" for every executed instruction to path, e.g. "12 0x204 0xD015 DRW V0, V1, 5"
+    pub fn start_trace_to_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let file = File::options().create(true).append(true).open(path)?;
+        self.stop_trace()?;
+        self.trace = Some(TraceWriter { out: BufWriter::new(file), lines: 0 });
+        Ok(())
+    }
+
+    pub fn stop_trace(&mut self) -> io::Result<()> {
+        match self.trace.take() {
+            Some(mut trace) => trace.out.flush(),
+            None => Ok(())
+        }
     }
-}
\ No newline at end of file
+
+    // class is the opcode's high nibble, e.g. 0xD for draws, replaces any hook already on that class
+    // the hook sees the machine after fetch (pc already past the opcode) and before it executes
+    // it runs once per executed instruction, a draw held back by set_max_draws_per_frame only counts when it finally runs
+    pub fn on_opcode(&mut self, class: u8, hook: OpcodeHook) {
+        self.opcode_hooks[(class & 0xF) as usize] = Some(hook);
+    }
+
+    fn run_opcode_hook(&mut self, opcode: u16) {
+        let class = (opcode >> 12) as usize;
+        // taken out for the call so the hook can borrow the emulator
+        if let Some(mut hook) = self.opcode_hooks[class].take() {
+            hook(self, opcode);
+            self.opcode_hooks[class] = Some(hook);
+        }
+    }
+
+    // tracing is best effort, a failed write stops the trace rather than the emulator
+    fn write_trace(&mut self, pc: u16, opcode: u16) {
+        let frame = self.frame;
+        if let Some(trace) = self.trace.as_mut() {
+            let mut result = writeln!(trace.out, "{} 0x{:03X} 0x{:04X} {}", frame, pc, opcode, disassemble(opcode));
+            trace.lines += 1;
+            if result.is_ok() && trace.lines % TRACE_FLUSH_LINES == 0 {
+                result = trace.out.flush();
+            }
+            if result.is_err() {
+                self.trace = None;
+            }
+        }
+    }
+
+    // linear listing of the next n two byte slots from pc, doesn't follow jumps so data may show up as code
+    pub fn upcoming(&self, n: usize) -> Vec<(u16, String)> {
+        (self.pc as usize..RAM_SIZE - 1)
+            .step_by(2)
+            .take(n)
+            .map(|addr| {
+                let op = ((self.ram[addr] as u16) << 8) | self.ram[addr + 1] as u16;
+                (addr as u16, disassemble(op))
+            })
+            .collect()
+    }
+
+    // (d1, d2, d3, d4) of the opcode at pc, most significant first, same split execute uses
+    pub fn current_nibbles(&self) -> (u8, u8, u8, u8) {
+        let op = self.peek_opcode();
+        ((op >> 12) as u8, ((op >> 8) & 0xF) as u8, ((op >> 4) & 0xF) as u8, (op & 0xF) as u8)
+    }
+
+    pub fn pipeline_view(&self) -> PipelineView {
+        let opcode = self.peek_opcode();
+        let (d1, d2, d3, d4) = self.current_nibbles();
+        PipelineView {
+            pc: self.pc,
+            bytes: opcode.to_be_bytes(),
+            opcode,
+            d1,
+            d2,
+            d3,
+            d4,
+            decoded: Opcode::decode(opcode)
+        }
+    }
+
+    // run until the current subroutine returns to its caller, errors if that takes more than max_cycles
+    pub fn step_out(&mut self, max_cycles: usize) -> Result<(), Chip8Error> {
+        let depth = self.stack_ptr;
+        for _ in 0..max_cycles {
+            self.tick()?;
+            if self.stack_ptr < depth {
+                return Ok(());
+            }
+        }
+        Err(Chip8Error::BudgetExceeded)
+    }
+
+    // a CALL at pc runs until it returns back to this depth, anything else is a single tick
+    pub fn step_over(&mut self, max_cycles: usize) -> Result<(), Chip8Error> {
+        if !matches!(Opcode::decode(self.peek_opcode()), Some(Opcode::Call(_))) {
+            return self.tick();
+        }
+        let depth = self.stack_ptr;
+        for _ in 0..max_cycles {
+            self.tick()?;
+            if self.stack_ptr == depth {
+                return Ok(());
+            }
+        }
+        Err(Chip8Error::BudgetExceeded)
+    }
+
+    // tick until something is drawn, true if the display changed before max_cycles ran out
+    // a redraw the frontend hasn't consumed yet stays flagged, only changes made during this call count towards the result
+    pub fn run_until_display_change(&mut self, max_cycles: usize) -> Result<bool, Chip8Error> {
+        let was_dirty = self.display_dirty;
+        self.display_dirty = false;
+        let mut result = Ok(false);
+        for _ in 0..max_cycles {
+            if let Err(err) = self.tick() {
+                result = Err(err);
+                break;
+            }
+            if self.display_dirty {
+                result = Ok(true);
+                break;
+            }
+        }
+        self.display_dirty |= was_dirty;
+        result
+    }
+
+    // raw 60Hz timer counts, for netplay rollback and anything else that needs exact values
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_t
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_t
+    }
+
+    pub fn set_delay_timer(&mut self, ticks: u8) {
+        self.delay_t = ticks;
+    }
+
+    pub fn set_sound_timer(&mut self, ticks: u8) {
+        self.sound_t = ticks;
+    }
+
+    // how long a frontend should sleep to hold target_hz frames a second after a frame that took elapsed, zero when behind
+    pub fn frame_budget(&self, target_hz: u32, elapsed: Duration) -> Duration {
+        if target_hz == 0 {
+            return Duration::ZERO; // no target to hold
+        }
+        (Duration::from_secs(1) / target_hz).saturating_sub(elapsed)
+    }
+
+    // real time left before the delay timer reaches 0
+    pub fn delay_remaining(&self) -> Duration {
+        timer_duration(self.delay_t)
+    }
+
+    // rounds to the nearest 60Hz tick, anything longer than the timer can hold is capped at 255 ticks
+    pub fn set_delay_duration(&mut self, d: Duration) {
+        self.delay_t = duration_ticks(d);
+    }
+
+    // errors unless both opcode bytes at a jump, call or skip target are inside ram
+    // checked before pc moves so the faulting instruction is left as it was
+    fn check_pc(&self, target: u16) -> Result<(), Chip8Error> {
+        if target as usize + 1 >= RAM_SIZE {
+            return Err(Chip8Error::PcOutOfBounds);
+        }
+        Ok(())
+    }
+
+    // errors unless all of start..start+len is inside ram
+    fn check_ram_range(&self, start: usize, len: usize) -> Result<(), Chip8Error> {
+        if start + len > RAM_SIZE {
+            return Err(Chip8Error::MemoryOutOfBounds);
+        }
+        Ok(())
+    }
+
+    // any overlap with the font counts, not just the first byte
+    fn check_font_write(&self, start: usize, len: usize) -> Result<(), Chip8Error> {
+        if self.protect_font && start < FONT_ADDR + FONTSET_SIZE && start + len > FONT_ADDR {
+            return Err(Chip8Error::ProtectedRegion);
+        }
+        Ok(())
+    }
+
+    // real time left before the sound timer reaches 0, for beep meters
+    pub fn sound_remaining(&self) -> Duration {
+        timer_duration(self.sound_t)
+    }
+
+    // stack operations
+    pub fn push(&mut self, val:u16) {
+        self.stack[self.stack_ptr as usize] = val; // change the current stack pointer to the val
+        self.stack_ptr += 1 // increase the stack pointer after modification
+    }
+
+    pub fn pop(&mut self) -> u16 {
+        self.stack_ptr -= 1; // move to previous position
+        self.stack[self.stack_ptr as usize] // return the value of the stack at the pointer
+    }
+
+    // most recently pushed return address, without popping it
+    pub fn stack_peek(&self) -> Option<u16> {
+        self.call_stack().last().copied()
+    }
+
+    // active frames, oldest first
+    pub fn call_stack(&self) -> &[u16] {
+        &self.stack[..self.stack_ptr as usize]
+    }
+
+    // bit n set if Vn holds a different value than before the last tick, writing the value a register already held doesn't count
+    pub fn changed_registers(&self) -> u16 {
+        self.changed_registers
+    }
+
+    // subroutine nesting level, 0 when running top level code
+    pub fn call_depth(&self) -> u16 {
+        self.stack_ptr
+    }
+
+    // interaction operations
+    pub fn get_display(&self) -> &[bool] {
+        &self.display
+    }
+
+    // display as grid[y][x], SCREEN_HEIGHT rows of SCREEN_WIDTH pixels
+    pub fn display_grid(&self) -> Vec<Vec<bool>> {
+        self.display.chunks(SCREEN_WIDTH).map(|row| row.to_vec()).collect()
+    }
+
+    pub fn display_snapshot(&self) -> Vec<bool> {
+        self.display.to_vec()
+    }
+
+    pub fn restore_display(&mut self, snapshot: &[bool]) -> Result<(), Chip8Error> {
+        if snapshot.len() != self.display.len() {
+            return Err(Chip8Error::DisplaySizeMismatch);
+        }
+        self.display.copy_from_slice(snapshot);
+        self.display_dirty = true;
+        Ok(())
+    }
+
+    // lit pixels on screen
+    pub fn pixel_count(&self) -> usize {
+        self.display.iter().filter(|&&on| on).count()
+    }
+
+    pub fn load_test_pattern(&mut self, pattern: TestPattern) {
+        for (idx, pixel) in self.display.iter_mut().enumerate() {
+            let (x, y) = display_coords(idx);
+            *pixel = match pattern {
+                TestPattern::Checkerboard => (x + y) % 2 == 0,
+                TestPattern::Border => x == 0 || y == 0 || x == SCREEN_WIDTH - 1 || y == SCREEN_HEIGHT - 1,
+                TestPattern::AllOn => true,
+                TestPattern::AllOff => false
+            };
+        }
+        self.display_dirty = true;
+    }
+
+    // heuristic, true once the screen has alternated between the same two frames for a few frames running
+    // a frontend can use it to switch on some fade to hide the flicker
+    pub fn flicker_detected(&self) -> bool {
+        self.flicker_frames >= FLICKER_FRAMES
+    }
+
+    pub fn is_display_dirty(&self) -> bool {
+        self.display_dirty
+    }
+
+    pub fn clear_display_dirty(&mut self) {
+        self.display_dirty = false;
+    }
+
+    // pack the display into bytes, row major, the leftmost pixel of each group of 8 is the most significant bit (same layout as sprites)
+    pub fn get_packed_display(&self) -> Vec<u8> {
+        self.display
+            .chunks(8)
+            .map(|pixels| pixels.iter().fold(0u8, |byte, &on| (byte << 1) | on as u8))
+            .collect()
+    }
+
+    // compare against a previously packed frame and return only the (byte idx, new value) pairs that changed
+    // the receiver applies these to its copy of the previous frame to rebuild the current one
+    // bytes missing from previous (e.g. an empty first frame) are always sent
+    pub fn display_delta(&self, previous: &[u8]) -> Vec<(u16, u8)> {
+        self.get_packed_display()
+            .into_iter()
+            .enumerate()
+            .filter(|&(i, byte)| previous.get(i) != Some(&byte))
+            .map(|(i, byte)| (i as u16, byte))
+            .collect()
+    }
+
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    // pixel that ends up at screen position (x, y) once the orientation is applied
+    fn oriented_pixel(&self, x: usize, y: usize) -> bool {
+        let (src_x, src_y) = match self.orientation {
+            Orientation::Normal => (x, y),
+            Orientation::FlipH => (SCREEN_WIDTH - 1 - x, y),
+            Orientation::FlipV => (x, SCREEN_HEIGHT - 1 - y),
+            Orientation::Rotate180 => (SCREEN_WIDTH - 1 - x, SCREEN_HEIGHT - 1 - y)
+        };
+        self.display[display_index(src_x, src_y)]
+    }
+
+    // nearest neighbour upscale into a (width*scale) x (height*scale) buffer, each pixel becomes a scale x scale block
+    // errors with DisplaySizeMismatch if out is not exactly that size, InvalidScale for 0 or a scale too big to size a buffer for
+    // out is left untouched on any error
+    pub fn render_rgba_scaled(&self, scale: usize, on: u32, off: u32, out: &mut [u32]) -> Result<(), Chip8Error> {
+        if scale == 0 {
+            return Err(Chip8Error::InvalidScale);
+        }
+        // out_width fitting means SCREEN_HEIGHT * scale does too, only the total can still overflow
+        let out_width = SCREEN_WIDTH.checked_mul(scale).ok_or(Chip8Error::InvalidScale)?;
+        let out_len = out_width.checked_mul(SCREEN_HEIGHT * scale).ok_or(Chip8Error::InvalidScale)?;
+        if out.len() != out_len {
+            return Err(Chip8Error::DisplaySizeMismatch);
+        }
+
+        for (i, out_row) in out.chunks_mut(out_width).enumerate() {
+            let y = i / scale;
+            for (j, color) in out_row.iter_mut().enumerate() {
+                let x = j / scale;
+                *color = if self.oriented_pixel(x, y) { on } else { off };
+            }
+        }
+        Ok(())
+    }
+
+    // indexes past the 16 keys are ignored so a frontend with a loose key mapping can't crash the emulator
+    pub fn keypress(&mut self, idx:usize, pressed:bool) {
+        if let Some(key) = self.keys.get_mut(idx) {
+            *key = pressed;
+        }
+    }
+
+    // apply a frame's worth of (key, pressed) changes together, meant to be called once per frame before running it
+    // so every instruction in the frame sees the same key state, later events for the same key win
+    pub fn apply_input(&mut self, events: &[(usize, bool)]) {
+        for &(idx, pressed) in events {
+            self.keypress(idx, pressed);
+        }
+    }
+
+    // ram operations
+    pub fn load(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        check_rom(data)?;
+        let start = START_ADDR as usize;
+        let end = (START_ADDR as usize) + data.len();
+        self.ram[start..end].copy_from_slice(data);
+        self.rom_len = data.len();
+        Ok(())
+    }
+
+    pub fn region_of(&self, addr: u16) -> MemRegion {
+        let addr = addr as usize;
+        let start = START_ADDR as usize;
+        if (FONT_ADDR..FONT_ADDR + FONTSET_SIZE).contains(&addr) {
+            MemRegion::Font
+        } else if addr >= start && addr < start + self.rom_len {
+            MemRegion::Program
+        } else {
+            MemRegion::Free
+        }
+    }
+
+    // all of ram, font and rom included, for memory viewers and other tooling
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn read_u16(&self, addr: u16, endian: Endian) -> Result<u16, Chip8Error> {
+        Ok(self.read_u16_slice(addr, 1, endian)?[0])
+    }
+
+    // count consecutive 16 bit values starting at addr
+    pub fn read_u16_slice(&self, addr: u16, count: usize, endian: Endian) -> Result<Vec<u16>, Chip8Error> {
+        let start = addr as usize;
+        self.check_ram_range(start, count * 2)?;
+        let values = self.ram[start..start + count * 2]
+            .chunks_exact(2)
+            .map(|pair| match endian {
+                Endian::Big => u16::from_be_bytes([pair[0], pair[1]]),
+                Endian::Little => u16::from_le_bytes([pair[0], pair[1]])
+            })
+            .collect();
+        Ok(values)
+    }
+
+    // decode a base64 rom and load it, returns the number of bytes loaded
+    #[cfg(feature = "base64")]
+    pub fn load_base64(&mut self, s: &str) -> Result<usize, Chip8Error> {
+        let data = base64::decode(s)?;
+        self.load(&data)?;
+        Ok(data.len())
+    }
+
+    // load a different game without rebuilding the emulator, cpu state is reset but quirks and other settings are kept
+    // a rom that can't be loaded is rejected before the reset so the running game carries on
+    pub fn swap_rom(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        check_rom(data)?;
+        self.reset();
+        self.load(data)
+    }
+
+    // stable 64 bit FNV-1a hash of the loaded program bytes, used to key saves and settings to a rom
+    // hand rolled since std's hasher is not guaranteed to be the same across releases
+    pub fn rom_hash(&self) -> u64 {
+        let start = START_ADDR as usize;
+        fnv1a(self.ram[start..start + self.rom_len].iter().copied())
+    }
+
+    // everything stuck loop detection compares, settings and counters left out
+    fn state_hash(&self) -> u64 {
+        let words = [self.pc, self.i_register, self.stack_ptr].into_iter().chain(self.stack).flat_map(u16::to_be_bytes);
+        let bytes = self.v_registers.into_iter()
+            .chain([self.delay_t, self.sound_t])
+            .chain(self.keys.iter().map(|&key| key as u8))
+            .chain(self.ram)
+            .chain(self.display.iter().map(|&on| on as u8));
+        fnv1a(words.chain(bytes))
+    }
+}
+
+// the checks load makes before touching ram
+fn check_rom(data: &[u8]) -> Result<(), Chip8Error> {
+    if data.is_empty() { // nothing to run, pc would just spin on zeroed ram
+        return Err(Chip8Error::EmptyRom);
+    }
+    if data.len() > RAM_SIZE - START_ADDR as usize { // exactly filling ram up to RAM_SIZE is fine
+        return Err(Chip8Error::RomTooLarge);
+    }
+    Ok(())
+}
+
+// true if bytes hash to expected_hash the same way rom_hash does, for spotting bad downloads
+pub fn verify_rom(bytes: &[u8], expected_hash: u64) -> bool {
+    fnv1a(bytes.iter().copied()) == expected_hash
+}
+
+// position in the row major display buffer of pixel (x, y), both must already be on screen
+pub fn display_index(x: usize, y: usize) -> usize {
+    x + SCREEN_WIDTH * y
+}
+
+// (x, y) of a display buffer index, inverse of display_index
+pub fn display_coords(idx: usize) -> (usize, usize) {
+    (idx % SCREEN_WIDTH, idx / SCREEN_WIDTH)
+}
+
+// (row, col) of a hex key in KEYPAD_LAYOUT, None for anything above 0xF
+pub fn keypad_position(key: u8) -> Option<(usize, usize)> {
+    KEYPAD_LAYOUT.iter().enumerate().find_map(|(row, keys)| {
+        keys.iter().position(|&k| k == key).map(|col| (row, col))
+    })
+}
+
+fn timer_duration(ticks: u8) -> Duration {
+    Duration::from_nanos(ticks as u64 * 1_000_000_000 / TIMER_HZ)
+}
+
+fn duration_ticks(d: Duration) -> u8 {
+    (d.as_secs_f64() * TIMER_HZ as f64).round().min(u8::MAX as f64) as u8
+}
+
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes.into_iter().fold(OFFSET_BASIS, |hash, byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // emulator with rom loaded at START_ADDR
+    fn emu_with(rom: &[u8]) -> Emulator {
+        Emulator::from_rom(rom).expect("test rom should load")
+    }
+
+    // pc after running a single skip opcode with V1 = a and V2 = b
+    fn pc_after_skip(op: u16, a: u8, b: u8) -> u16 {
+        let mut emu = emu_with(&op.to_be_bytes());
+        emu.v_registers[1] = a;
+        emu.v_registers[2] = b;
+        emu.tick().unwrap();
+        emu.pc
+    }
+
+    #[test]
+    fn skip_eq_imm() {
+        assert_eq!(pc_after_skip(0x3105, 5, 0), 0x204);
+        assert_eq!(pc_after_skip(0x3105, 6, 0), 0x202);
+    }
+
+    #[test]
+    fn skip_ne_imm() {
+        assert_eq!(pc_after_skip(0x4105, 6, 0), 0x204);
+        assert_eq!(pc_after_skip(0x4105, 5, 0), 0x202);
+        // used to compare VX with VY, which would have skipped here
+        assert_eq!(pc_after_skip(0x4105, 5, 5), 0x202);
+    }
+
+    #[test]
+    fn skip_eq_reg() {
+        assert_eq!(pc_after_skip(0x5120, 7, 7), 0x204);
+        assert_eq!(pc_after_skip(0x5120, 7, 8), 0x202);
+        // used to compare VX with NN (0x20), which would have skipped here
+        assert_eq!(pc_after_skip(0x5120, 0x21, 0x22), 0x202);
+    }
+
+    #[test]
+    fn skip_ne_reg() {
+        assert_eq!(pc_after_skip(0x9120, 7, 8), 0x204);
+        assert_eq!(pc_after_skip(0x9120, 7, 7), 0x202);
+    }
+
+    #[test]
+    fn display_delta_lists_changed_packed_bytes() {
+        let mut emu = Emulator::new();
+        let previous = emu.get_packed_display();
+        emu.display[0] = true; // byte 0
+        emu.display[9] = true; // byte 1
+        emu.display[display_index(63, 31)] = true; // last byte
+        assert_eq!(emu.display_delta(&previous), vec![(0, 0x80), (1, 0x40), (255, 0x01)]);
+        assert!(emu.display_delta(&emu.get_packed_display()).is_empty());
+    }
+
+    #[test]
+    fn explain_step_reports_add() {
+        let mut emu = emu_with(&[0x71, 0x05]); // ADD V1, 0x05
+        emu.v_registers[1] = 3;
+        let report = emu.explain_step().unwrap();
+        assert_eq!(report.pc, 0x200);
+        assert_eq!(report.opcode, 0x7105);
+        assert_eq!(report.mnemonic, "ADD V1, 0x05");
+        assert_eq!(report.next_pc, 0x202);
+        assert_eq!(report.changes, vec![StateChange::Register { index: 1, old: 3, new: 8 }]);
+    }
+
+    #[test]
+    fn font_lookup_masks_vx() {
+        let mut emu = emu_with(&[0xF3, 0x29]); // LD F, V3
+        emu.v_registers[3] = 0x1A;
+        emu.tick().unwrap();
+        assert_eq!(emu.i_register, (FONT_ADDR + 0xA * GLYPH_SIZE) as u16);
+    }
+
+    #[test]
+    fn render_scaled_replicates_pixels() {
+        let mut emu = Emulator::new();
+        emu.display[display_index(3, 1)] = true;
+        let mut out = vec![0; SCREEN_WIDTH * 2 * SCREEN_HEIGHT * 2];
+        emu.render_rgba_scaled(2, 1, 0, &mut out).unwrap();
+        let lit: Vec<usize> = (0..out.len()).filter(|&i| out[i] == 1).collect();
+        let row = SCREEN_WIDTH * 2;
+        assert_eq!(lit, vec![2 * row + 6, 2 * row + 7, 3 * row + 6, 3 * row + 7]);
+    }
+
+    #[test]
+    fn render_scaled_rejects_bad_buffer_and_scale() {
+        let emu = Emulator::new();
+        let mut short = vec![0; 10];
+        assert_eq!(emu.render_rgba_scaled(2, 1, 0, &mut short), Err(Chip8Error::DisplaySizeMismatch));
+        let mut empty: Vec<u32> = Vec::new();
+        assert_eq!(emu.render_rgba_scaled(0, 1, 0, &mut empty), Err(Chip8Error::InvalidScale));
+        assert_eq!(emu.render_rgba_scaled(usize::MAX, 1, 0, &mut empty), Err(Chip8Error::InvalidScale));
+        assert_eq!(emu.render_rgba_scaled(usize::MAX / SCREEN_WIDTH, 1, 0, &mut empty), Err(Chip8Error::InvalidScale));
+    }
+
+    #[test]
+    fn rom_hash_is_stable() {
+        let emu = emu_with(&[0x00, 0xE0, 0x12, 0x00]);
+        assert_eq!(emu.rom_hash(), 0xe375_c27c_8d02_e1f7);
+    }
+
+    #[test]
+    fn empty_rom_is_rejected() {
+        let mut emu = Emulator::new();
+        assert_eq!(emu.load(&[]), Err(Chip8Error::EmptyRom));
+    }
+
+    #[test]
+    fn cycle_budget_errors_after_the_limit() {
+        let mut emu = emu_with(&[0x12, 0x00]); // JMP 0x200
+        emu.set_max_total_cycles(Some(100));
+        for _ in 0..100 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(emu.tick(), Err(Chip8Error::BudgetExceeded));
+        assert_eq!(emu.total_cycles(), 100);
+    }
+
+    #[test]
+    fn empty_ret_halts_when_enabled() {
+        let mut emu = emu_with(&[0x00, 0xEE]);
+        emu.set_halt_on_stack_error(true);
+        assert_eq!(emu.tick(), Ok(()));
+        assert!(emu.is_halted());
+        assert_eq!(emu.last_error(), Some(Chip8Error::StackUnderflow));
+        assert_eq!(emu.pc, 0x200);
+        emu.tick().unwrap(); // halted, nothing runs
+        assert_eq!(emu.total_cycles(), 0);
+    }
+
+    #[test]
+    fn empty_ret_errors_by_default() {
+        let mut emu = emu_with(&[0x00, 0xEE]);
+        assert_eq!(emu.tick(), Err(Chip8Error::StackUnderflow));
+        assert!(!emu.is_halted());
+    }
+
+    #[test]
+    fn call_with_full_stack_overflows() {
+        let mut emu = emu_with(&[0x22, 0x00]); // CALL 0x200, recursing forever
+        for _ in 0..STACK_SIZE {
+            emu.tick().unwrap();
+        }
+        assert_eq!(emu.tick(), Err(Chip8Error::StackOverflow));
+        assert_eq!(emu.call_depth() as usize, STACK_SIZE);
+        assert!(!emu.is_halted());
+    }
+
+    #[test]
+    fn call_with_full_stack_halts_when_enabled() {
+        let mut emu = emu_with(&[0x22, 0x00]);
+        emu.set_halt_on_stack_error(true);
+        for _ in 0..=STACK_SIZE {
+            emu.tick().unwrap();
+        }
+        assert!(emu.is_halted());
+        assert_eq!(emu.last_error(), Some(Chip8Error::StackOverflow));
+        assert_eq!(emu.pc, START_ADDR); // left on the faulting CALL
+        assert_eq!(emu.call_depth() as usize, STACK_SIZE);
+        emu.tick().unwrap();
+        assert_eq!(emu.total_cycles(), STACK_SIZE as u64);
+    }
+
+    // 200: CALL 206, 202: JMP 202, 204: unused, 206: LD V1 1, 208: LD V2 2, 20A: RET
+    const SUBROUTINE_ROM: [u8; 12] = [0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x61, 0x01, 0x62, 0x02, 0x00, 0xEE];
+
+    #[test]
+    fn step_out_returns_to_caller() {
+        let mut emu = emu_with(&SUBROUTINE_ROM);
+        emu.tick().unwrap(); // into the subroutine
+        emu.tick().unwrap();
+        emu.step_out(100).unwrap();
+        assert_eq!(emu.pc, 0x202);
+        assert_eq!(emu.call_depth(), 0);
+        assert_eq!(emu.v_registers[2], 2);
+    }
+
+    #[test]
+    fn step_out_gives_up_after_the_budget() {
+        let mut emu = emu_with(&[0x22, 0x04, 0x00, 0x00, 0x12, 0x04]); // CALL into a routine that never returns
+        emu.tick().unwrap();
+        assert_eq!(emu.step_out(10), Err(Chip8Error::BudgetExceeded));
+    }
+
+    #[test]
+    fn step_over_runs_the_whole_call() {
+        let mut emu = emu_with(&SUBROUTINE_ROM);
+        emu.step_over(100).unwrap();
+        assert_eq!(emu.pc, 0x202);
+        assert_eq!(emu.call_depth(), 0);
+        assert_eq!((emu.v_registers[1], emu.v_registers[2]), (1, 2));
+    }
+
+    #[test]
+    fn step_over_is_a_single_tick_for_other_opcodes() {
+        let mut emu = emu_with(&[0x61, 0x01, 0x62, 0x02]);
+        emu.step_over(100).unwrap();
+        assert_eq!(emu.pc, 0x202);
+        assert_eq!(emu.total_cycles(), 1);
+    }
+
+    #[test]
+    fn call_stack_lists_frames_oldest_first() {
+        let mut emu = emu_with(&[0x22, 0x04, 0x00, 0x00, 0x22, 0x08, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(emu.stack_peek(), None);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert_eq!(emu.call_stack(), &[0x202, 0x206]);
+        assert_eq!(emu.stack_peek(), Some(0x206));
+        assert_eq!(emu.call_depth(), 2); // peeking never pops
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[test]
+    fn execute_opcode_skips_fetch() {
+        let mut emu = Emulator::new();
+        emu.execute_opcode(0x6A42).unwrap();
+        assert_eq!(emu.v_registers[0xA], 0x42);
+        assert_eq!(emu.pc, START_ADDR);
+        assert!(emu.ram[START_ADDR as usize..].iter().all(|&byte| byte == 0));
+    }
+
+    // lit pixels after drawing a 2x2 block at (63, 31), so it crosses the right and bottom edges
+    fn corner_block(wrap_x: bool, wrap_y: bool) -> Vec<(usize, usize)> {
+        let mut emu = emu_with(&[0xA3, 0x00, 0xD1, 0x22]); // LD I, 0x300 / DRW V1, V2, 2
+        emu.set_quirks(Quirks { wrap_x, wrap_y, ..Quirks::default() });
+        emu.ram[0x300] = 0xC0;
+        emu.ram[0x301] = 0xC0;
+        emu.v_registers[1] = 63;
+        emu.v_registers[2] = 31;
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        (0..emu.display.len()).filter(|&idx| emu.display[idx]).map(display_coords).collect()
+    }
+
+    #[test]
+    fn sprite_wraps_on_both_axes() {
+        assert_eq!(corner_block(true, true), vec![(0, 0), (63, 0), (0, 31), (63, 31)]);
+    }
+
+    #[test]
+    fn sprite_wraps_x_and_clips_y() {
+        assert_eq!(corner_block(true, false), vec![(0, 31), (63, 31)]);
+    }
+
+    #[test]
+    fn sprite_clips_x_and_wraps_y() {
+        assert_eq!(corner_block(false, true), vec![(63, 0), (63, 31)]);
+    }
+
+    #[test]
+    fn sprite_clips_on_both_axes() {
+        assert_eq!(corner_block(false, false), vec![(63, 31)]);
+    }
+
+    // three NOPs, then LD I, 0x000 / DRW V0, V0, 5
+    const DRAW_AFTER_NOPS_ROM: [u8; 10] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xA0, 0x00, 0xD0, 0x05];
+
+    #[test]
+    fn run_until_display_change_stops_after_the_draw() {
+        let mut emu = emu_with(&DRAW_AFTER_NOPS_ROM);
+        assert_eq!(emu.run_until_display_change(100), Ok(true));
+        assert_eq!(emu.pc, 0x20A);
+        assert!(emu.is_display_dirty());
+    }
+
+    #[test]
+    fn run_until_display_change_keeps_an_unconsumed_redraw() {
+        let mut emu = emu_with(&DRAW_AFTER_NOPS_ROM);
+        emu.run_until_display_change(100).unwrap();
+        // nothing else draws, but the frontend never cleared the flag
+        assert_eq!(emu.run_until_display_change(3), Ok(false));
+        assert!(emu.is_display_dirty());
+        emu.clear_display_dirty();
+        assert!(!emu.is_display_dirty());
+    }
+
+    #[test]
+    fn delay_remaining_in_real_time() {
+        let mut emu = Emulator::new();
+        emu.delay_t = 30;
+        assert_eq!(emu.delay_remaining(), Duration::from_millis(500));
+        emu.set_delay_duration(Duration::from_millis(250));
+        assert_eq!(emu.delay_t, 15);
+        emu.set_delay_duration(Duration::from_secs(60));
+        assert_eq!(emu.delay_t, u8::MAX);
+    }
+
+    #[test]
+    fn display_snapshot_round_trips() {
+        let mut emu = emu_with(&DRAW_AFTER_NOPS_ROM);
+        emu.run_until_display_change(100).unwrap();
+        let snapshot = emu.display_snapshot();
+        emu.display = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+        emu.restore_display(&snapshot).unwrap();
+        assert_eq!(emu.get_display(), &snapshot[..]);
+        assert_eq!(emu.restore_display(&snapshot[1..]), Err(Chip8Error::DisplaySizeMismatch));
+    }
+
+    #[test]
+    fn opcode_histogram_counts_classes() {
+        // LD V1, 1 / ADD V1, 1 / ADD V1, 1 / LD I, 0x300 / JMP 0x20A
+        let mut emu = emu_with(&[0x61, 0x01, 0x71, 0x01, 0x71, 0x01, 0xA3, 0x00, 0x12, 0x0A, 0x12, 0x0A]);
+        emu.tick().unwrap(); // before counting is on
+        emu.set_opcode_histogram_enabled(true);
+        for _ in 0..5 {
+            emu.tick().unwrap();
+        }
+        let histogram = emu.opcode_histogram();
+        assert_eq!(histogram.get(&0x7), Some(&2));
+        assert_eq!(histogram.get(&0xA), Some(&1));
+        assert_eq!(histogram.get(&0x1), Some(&2));
+        assert_eq!(histogram.get(&0x6), None);
+    }
+
+    #[test]
+    fn from_rom_loads_at_start_addr() {
+        let emu = emu_with(&[0xAB, 0xCD, 0xEF]);
+        assert_eq!(&emu.ram[0x200..0x203], &[0xAB, 0xCD, 0xEF]);
+        assert_eq!(emu.ram[0x203], 0);
+        assert_eq!(emu.pc, START_ADDR);
+        assert_eq!(Emulator::from_rom(&[]).err(), Some(Chip8Error::EmptyRom));
+    }
+
+    #[test]
+    fn unknown_opcode_errors_unless_lenient() {
+        let mut emu = emu_with(&[0x01, 0x23, 0x61, 0x07]);
+        assert_eq!(emu.tick(), Err(Chip8Error::UnknownOpcode(0x0123)));
+        assert_eq!(emu.pc, 0x200);
+
+        emu.set_lenient_unknown_opcodes(true);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert_eq!(emu.v_registers[1], 7);
+    }
+
+    #[test]
+    fn keypad_layout_has_every_key_once() {
+        assert_eq!(KEYPAD_LAYOUT[0][0], 0x1);
+        assert_eq!(KEYPAD_LAYOUT[0][3], 0xC);
+        assert_eq!(KEYPAD_LAYOUT[3][0], 0xA);
+        assert_eq!(KEYPAD_LAYOUT[3][3], 0xF);
+        for key in 0..=0xF {
+            let count = KEYPAD_LAYOUT.iter().flatten().filter(|&&k| k == key).count();
+            assert_eq!(count, 1, "key {:X}", key);
+        }
+        assert_eq!(keypad_position(0x0), Some((3, 1)));
+        assert_eq!(keypad_position(0x10), None);
+    }
+
+    #[test]
+    fn input_schedule_applies_at_frame_boundaries() {
+        let mut emu = Emulator::new();
+        emu.set_input_schedule(vec![(4, 5, false), (2, 5, true)]);
+        let mut held = Vec::new();
+        for _ in 0..5 {
+            emu.tick_timers();
+            held.push((emu.frame(), emu.keys[5]));
+        }
+        assert_eq!(held, vec![(1, false), (2, true), (3, true), (4, false), (5, false)]);
+    }
+
+    // LD I, 0x000 / DRW V0, V0, 5 twice, the "0" glyph drawn over itself
+    const DOUBLE_DRAW_ROM: [u8; 6] = [0xA0, 0x00, 0xD0, 0x05, 0xD0, 0x05];
+
+    #[test]
+    fn or_mode_keeps_pixels_on() {
+        let mut emu = emu_with(&DOUBLE_DRAW_ROM);
+        emu.set_draw_mode(DrawMode::Or);
+        for _ in 0..3 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(emu.pixel_count(), 14);
+        assert_eq!(emu.v_registers[0xF], 1); // overlap is still reported
+    }
+
+    #[test]
+    fn xor_mode_erases_on_redraw() {
+        let mut emu = emu_with(&DOUBLE_DRAW_ROM);
+        for _ in 0..3 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(emu.pixel_count(), 0);
+        assert_eq!(emu.v_registers[0xF], 1);
+    }
+
+    #[test]
+    fn beep_started_only_on_the_transition_to_zero() {
+        let mut emu = Emulator::new();
+        emu.sound_t = 2;
+        emu.delay_t = 5;
+        let first = emu.tick_timers();
+        assert_eq!(first, TimerTick { beep_started: false, delay_t: 4, sound_t: 1 });
+        assert!(emu.tick_timers().beep_started);
+        assert!(!emu.tick_timers().beep_started); // stays at 0 without firing again
+    }
+
+    #[test]
+    fn swap_rom_keeps_settings() {
+        let mut emu = emu_with(&[0x61, 0x01, 0x62, 0x02]);
+        let quirks = Quirks { wrap_x: false, ..Quirks::default() };
+        emu.set_quirks(quirks);
+        emu.tick().unwrap();
+        emu.swap_rom(&[0x63, 0x03]).unwrap();
+        assert_eq!(emu.quirks(), quirks);
+        assert_eq!(&emu.ram[0x200..0x204], &[0x63, 0x03, 0x00, 0x00]);
+        assert_eq!((emu.pc, emu.v_registers[1]), (START_ADDR, 0));
+    }
+
+    #[test]
+    fn key_wait_times_out() {
+        let mut emu = emu_with(&[0xF0, 0x0A]); // LD V0, K
+        emu.set_key_wait_timeout(Some(2));
+        emu.tick().unwrap();
+        assert!(emu.is_waiting_for_key());
+        emu.tick_timers();
+        emu.tick().unwrap();
+        emu.tick_timers();
+        assert_eq!(emu.tick(), Err(Chip8Error::InputTimeout));
+        assert!(!emu.is_waiting_for_key());
+    }
+
+    #[test]
+    fn key_wait_resolves_on_press() {
+        let mut emu = emu_with(&[0xF3, 0x0A]);
+        emu.tick().unwrap();
+        assert_eq!(emu.pc, 0x200);
+        emu.keypress(0xB, true);
+        emu.tick().unwrap();
+        assert_eq!((emu.pc, emu.v_registers[3]), (0x202, 0xB));
+    }
+
+    #[test]
+    fn upcoming_lists_from_pc() {
+        let mut emu = emu_with(&[0x12, 0x04, 0x00, 0x00, 0x61, 0x05, 0xD1, 0x23]);
+        emu.tick().unwrap();
+        assert_eq!(emu.upcoming(2), vec![(0x204, "LD V1, 0x05".to_string()), (0x206, "DRW V1, V2, 3".to_string())]);
+        emu.pc = 0xFFC;
+        assert_eq!(emu.upcoming(5).len(), 2); // stops where a whole opcode no longer fits
+    }
+
+    #[test]
+    fn audio_callback_follows_the_sound_timer() {
+        use std::sync::{Arc, Mutex};
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&calls);
+        let mut emu = Emulator::new();
+        emu.set_audio_callback(Box::new(move |on| recorded.lock().unwrap().push(on)));
+        emu.sound_t = 2;
+        for _ in 0..4 {
+            emu.tick_timers();
+        }
+        assert_eq!(*calls.lock().unwrap(), vec![true, false, false, false]);
+    }
+
+    #[test]
+    fn region_of_classifies_ram() {
+        let emu = emu_with(&[0x00, 0xE0, 0x12, 0x00]);
+        assert_eq!(emu.region_of(0x000), MemRegion::Font);
+        assert_eq!(emu.region_of(0x04F), MemRegion::Font);
+        assert_eq!(emu.region_of(0x050), MemRegion::Free);
+        assert_eq!(emu.region_of(0x200), MemRegion::Program);
+        assert_eq!(emu.region_of(0x203), MemRegion::Program);
+        assert_eq!(emu.region_of(0x204), MemRegion::Free);
+        assert_eq!(emu.region_of(0xFFF), MemRegion::Free);
+    }
+
+    #[test]
+    fn stores_and_bcd_near_the_top_of_ram_error() {
+        for op in [0xFF55u16, 0xFF65, 0xF033] {
+            let mut emu = emu_with(&op.to_be_bytes());
+            emu.i_register = (RAM_SIZE - 2) as u16;
+            assert_eq!(emu.tick(), Err(Chip8Error::MemoryOutOfBounds), "{:04X}", op);
+            assert!(emu.ram[RAM_SIZE - 2..].iter().all(|&byte| byte == 0), "nothing half written");
+        }
+        let mut emu = emu_with(&[0xF1, 0x55]);
+        emu.i_register = (RAM_SIZE - 2) as u16;
+        emu.tick().unwrap(); // exactly fits
+    }
+
+    #[test]
+    fn flip_h_mirrors_the_render() {
+        let mut emu = Emulator::new();
+        emu.display[display_index(0, 0)] = true;
+        emu.set_orientation(Orientation::FlipH);
+        let mut out = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        emu.render_rgba_scaled(1, 1, 0, &mut out).unwrap();
+        assert_eq!(out[SCREEN_WIDTH - 1], 1);
+        assert_eq!(out[0], 0);
+        assert!(emu.display[0], "the display buffer itself isn't flipped");
+    }
+
+    #[test]
+    fn out_of_range_keypress_is_ignored() {
+        let mut emu = Emulator::new();
+        emu.keypress(3, true);
+        emu.keypress(20, true);
+        emu.keypress(NUM_KEYS, true);
+        assert_eq!(emu.keys.iter().filter(|&&down| down).count(), 1);
+        assert!(emu.keys[3]);
+    }
+
+    #[test]
+    fn sound_remaining_in_real_time() {
+        let mut emu = Emulator::new();
+        emu.sound_t = 60;
+        assert_eq!(emu.sound_remaining(), Duration::from_secs(1));
+        emu.tick_timers();
+        assert!(emu.sound_remaining() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn tick_with_timers_matches_separate_calls() {
+        // LD V0, 5; LD DT, V0; JP 0x204
+        let rom = [0x60, 0x05, 0xF0, 0x15, 0x12, 0x04];
+        let mut helper = emu_with(&rom);
+        let mut manual = emu_with(&rom);
+        for frame in 0..5 {
+            let last = frame >= 2;
+            helper.tick_with_timers(last).unwrap();
+            manual.tick().unwrap();
+            if last {
+                manual.tick_timers();
+            }
+        }
+        assert_eq!(helper.delay_timer(), 2);
+        assert!(helper.state_eq(&manual));
+    }
+
+    #[test]
+    fn shifting_vf_leaves_the_flag_not_the_result() {
+        for (op, expected) in [(0x8FF6u16, 1), (0x8FFE, 1)] {
+            let mut emu = emu_with(&op.to_be_bytes());
+            emu.v_registers[0xF] = 0x81;
+            emu.tick().unwrap();
+            assert_eq!(emu.v_registers[0xF], expected, "{:04X}", op);
+        }
+        // the shifted out bit is 0 this time, the shifted value would be nonzero
+        for op in [0x8FF6u16, 0x8FFE] {
+            let mut emu = emu_with(&op.to_be_bytes());
+            emu.v_registers[0xF] = 0x42;
+            emu.tick().unwrap();
+            assert_eq!(emu.v_registers[0xF], 0, "{:04X}", op);
+        }
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn load_base64_puts_the_rom_at_the_start_address() {
+        let mut emu = Emulator::new();
+        assert_eq!(emu.load_base64("AOASAA=="), Ok(4));
+        let start = START_ADDR as usize;
+        assert_eq!(emu.ram[start..start + 4], [0x00, 0xE0, 0x12, 0x00]);
+    }
+
+    #[test]
+    fn draw_limit_defers_the_second_draw_to_the_next_frame() {
+        let mut emu = emu_with(&DOUBLE_DRAW_ROM);
+        emu.set_max_draws_per_frame(Some(1));
+        for _ in 0..3 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(emu.pc, START_ADDR + 4); // stuck on the second draw
+        assert_eq!(emu.pixel_count(), 14);
+        emu.tick_timers();
+        emu.tick().unwrap();
+        assert_eq!(emu.pc, START_ADDR + 6);
+        assert_eq!(emu.pixel_count(), 0);
+    }
+
+    #[test]
+    fn trace_writes_a_line_per_instruction() {
+        let path = std::env::temp_dir().join(format!("chip8_trace_test_{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        // CLS; JP 0x200
+        let mut emu = emu_with(&[0x00, 0xE0, 0x12, 0x00]);
+        emu.start_trace_to_file(&path).unwrap();
+        for _ in 0..3 {
+            emu.tick().unwrap();
+        }
+        emu.stop_trace().unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], format!("0 0x200 0x00E0 {}", disassemble(0x00E0)));
+        assert_eq!(lines[1], format!("0 0x202 0x1200 {}", disassemble(0x1200)));
+        assert!(lines[2].starts_with("0 0x200 0x00E0"));
+    }
+
+    #[test]
+    fn waiting_for_key_until_one_is_pressed() {
+        // LD V0, K
+        let mut emu = emu_with(&[0xF0, 0x0A]);
+        assert!(!emu.is_waiting_for_key());
+        emu.tick().unwrap();
+        assert!(emu.is_waiting_for_key());
+        emu.keypress(7, true);
+        emu.tick().unwrap();
+        assert!(!emu.is_waiting_for_key());
+        assert_eq!(emu.v_registers[0], 7);
+    }
+
+    #[test]
+    fn signed_bcd_writes_a_sign_byte() {
+        let mut emu = emu_with(&[0xF0, 0x33, 0xF0, 0x33]);
+        emu.v_registers[0] = 0xFF;
+        emu.i_register = 0x300;
+        emu.set_signed_bcd(true);
+        emu.tick().unwrap();
+        assert_eq!(emu.ram[0x300..0x304], [1, 0, 0, 1]); // -1
+        emu.set_signed_bcd(false);
+        emu.tick().unwrap();
+        assert_eq!(emu.ram[0x300..0x303], [2, 5, 5]);
+    }
+
+    #[test]
+    fn apply_input_later_events_win() {
+        let mut emu = Emulator::new();
+        emu.apply_input(&[(1, true), (2, true), (1, false), (NUM_KEYS, true)]);
+        assert!(!emu.keys[1]);
+        assert!(emu.keys[2]);
+        assert_eq!(emu.keys.iter().filter(|&&key| key).count(), 1);
+    }
+
+    #[test]
+    fn display_grid_is_rows_of_pixels() {
+        let mut emu = Emulator::new();
+        emu.display[display_index(3, 2)] = true;
+        let grid = emu.display_grid();
+        assert_eq!(grid.len(), SCREEN_HEIGHT);
+        assert!(grid.iter().all(|row| row.len() == SCREEN_WIDTH));
+        assert!(grid[2][3]);
+        assert!(!grid[3][2]);
+    }
+
+    #[test]
+    fn bnnn_past_the_end_of_ram_errors() {
+        // JP V0, 0xFFF
+        let mut emu = emu_with(&[0xBF, 0xFF]);
+        emu.v_registers[0] = 0x10;
+        assert_eq!(emu.tick(), Err(Chip8Error::PcOutOfBounds));
+        assert_eq!(emu.pc, START_ADDR);
+
+        // 0xFFE is the last address both opcode bytes fit behind
+        let mut emu = emu_with(&[0xBF, 0xF0]);
+        emu.v_registers[0] = 0x0E;
+        emu.tick().unwrap();
+        assert_eq!(emu.pc, 0xFFE);
+    }
+
+    #[test]
+    fn wrap_events_count_wrapped_pixels() {
+        // LD I, 0x000; DRW V0, V1, 5 with the "0" glyph straddling the right edge, twice
+        let rom = [0xA0, 0x00, 0xD0, 0x15, 0xD0, 0x15];
+        let mut emu = emu_with(&rom);
+        emu.v_registers[0] = 62;
+        emu.set_quirks(Quirks { wrap_x: true, ..emu.quirks() });
+        emu.set_wrap_event_counting(true);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert_eq!(emu.wrap_events(), 7); // columns 2 and 3 of the glyph
+        emu.set_wrap_event_counting(false);
+        emu.tick().unwrap();
+        assert_eq!(emu.wrap_events(), 7);
+
+        // clipped pixels aren't wrapped so don't count
+        let mut emu = emu_with(&rom);
+        emu.v_registers[0] = 62;
+        emu.set_quirks(Quirks { wrap_x: false, ..emu.quirks() });
+        emu.set_wrap_event_counting(true);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert_eq!(emu.wrap_events(), 0);
+    }
+
+    #[test]
+    fn flicker_detected_on_alternating_frames() {
+        let mut emu = Emulator::new();
+        for frame in 0..10 {
+            emu.display[0] = frame % 2 == 0;
+            emu.tick_timers();
+        }
+        assert!(emu.flicker_detected());
+        emu.tick_timers(); // same screen twice in a row
+        assert!(!emu.flicker_detected());
+    }
+
+    #[test]
+    fn steady_screen_is_not_flicker() {
+        let mut emu = Emulator::new();
+        emu.display[0] = true;
+        for _ in 0..10 {
+            emu.tick_timers();
+        }
+        assert!(!emu.flicker_detected());
+    }
+
+    #[test]
+    fn draw_hook_sees_only_draws() {
+        use std::sync::{Arc, Mutex};
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let hook_seen = Arc::clone(&seen);
+        let mut emu = emu_with(&DOUBLE_DRAW_ROM);
+        emu.on_opcode(0xD, Box::new(move |emu, opcode| hook_seen.lock().unwrap().push((emu.pc, opcode))));
+        for _ in 0..3 {
+            emu.tick().unwrap();
+        }
+        // pc is already past the draw when the hook runs
+        assert_eq!(*seen.lock().unwrap(), vec![(START_ADDR + 4, 0xD005), (START_ADDR + 6, 0xD005)]);
+    }
+
+    #[test]
+    fn tick_scaled_runs_the_timers_every_n_ticks() {
+        let mut emu = emu_with(&[0x12, 0x00]);
+        emu.delay_t = 10;
+        for _ in 0..8 {
+            emu.tick_scaled(3).unwrap();
+        }
+        assert_eq!(emu.delay_timer(), 8);
+        emu.tick_scaled(3).unwrap();
+        assert_eq!(emu.delay_timer(), 7);
+        assert_eq!(emu.frame(), 3);
+    }
+
+    #[test]
+    fn state_eq_ignores_settings_and_stale_stack() {
+        let mut a = emu_with(&SUBROUTINE_ROM);
+        let mut b = emu_with(&SUBROUTINE_ROM);
+        b.set_draw_mode(DrawMode::Or);
+        b.set_opcode_histogram_enabled(true);
+        assert!(a.state_eq(&b));
+
+        // CALL then RET leaves the return address behind the stack pointer in a only
+        for _ in 0..4 {
+            a.tick().unwrap();
+        }
+        b.pc = a.pc;
+        b.v_registers = a.v_registers;
+        assert!(a.state_eq(&b));
+
+        b.v_registers[3] = 1;
+        assert!(!a.state_eq(&b));
+    }
+
+    #[test]
+    fn run_to_next_event_reports_a_key_wait() {
+        // LD V0, K
+        let mut emu = emu_with(&[0xF0, 0x0A]);
+        assert_eq!(emu.run_to_next_event(100), Ok(Event::AwaitingKey));
+        assert_eq!(emu.pc, START_ADDR);
+    }
+
+    #[test]
+    fn run_to_next_event_keeps_an_unconsumed_redraw() {
+        let mut emu = emu_with(&[0xF0, 0x0A]);
+        emu.display_dirty = true;
+        assert_eq!(emu.run_to_next_event(100), Ok(Event::AwaitingKey)); // the old redraw isn't a new event
+        assert!(emu.is_display_dirty());
+
+        let mut emu = emu_with(&DOUBLE_DRAW_ROM);
+        assert_eq!(emu.run_to_next_event(100), Ok(Event::Draw));
+        assert!(emu.is_display_dirty());
+    }
+
+    #[test]
+    fn fx29_points_at_each_glyph_in_the_small_font() {
+        for digit in 0..16u8 {
+            let mut emu = emu_with(&[0xF0, 0x29]);
+            emu.v_registers[0] = digit;
+            emu.tick().unwrap();
+            let addr = emu.i_register as usize;
+            assert_eq!(addr, FONT_ADDR + digit as usize * GLYPH_SIZE);
+            assert!(addr + GLYPH_SIZE <= FONT_ADDR + FONTSET_SIZE);
+            let glyph = &FONTSET[digit as usize * GLYPH_SIZE..][..GLYPH_SIZE];
+            assert_eq!(&emu.ram[addr..addr + GLYPH_SIZE], glyph);
+        }
+    }
+
+    #[test]
+    fn collision_map_lists_pixels_drawn_over() {
+        // LD I, 0x000; DRW V0, V0, 5; LD V0, 1; DRW V0, V0, 5, the "0" glyph over a copy shifted down and right by one
+        let mut emu = emu_with(&[0xA0, 0x00, 0xD0, 0x05, 0x60, 0x01, 0xD0, 0x05]);
+        emu.set_collision_recording(true);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert!(emu.last_collision_map().is_empty());
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        let map = emu.last_collision_map();
+        assert_eq!(map, vec![(3, 1), (1, 4)]);
+    }
+
+    #[test]
+    fn collision_map_stays_empty_when_off() {
+        let mut emu = emu_with(&DOUBLE_DRAW_ROM);
+        for _ in 0..3 {
+            emu.tick().unwrap();
+        }
+        assert!(emu.last_collision_map().is_empty());
+    }
+
+    #[test]
+    fn rom_may_fill_ram_exactly() {
+        let max = RAM_SIZE - START_ADDR as usize;
+        let mut emu = Emulator::new();
+        let mut rom = vec![0x00; max];
+        rom[max - 1] = 0xAB;
+        assert_eq!(emu.load(&rom), Ok(()));
+        assert_eq!(emu.ram[RAM_SIZE - 1], 0xAB);
+
+        let mut emu = Emulator::new();
+        assert_eq!(emu.load(&vec![0x00; max + 1]), Err(Chip8Error::RomTooLarge));
+    }
+
+    #[test]
+    fn timer_values_restore_mid_countdown() {
+        let mut emu = emu_with(&[0x12, 0x00]);
+        emu.set_delay_timer(30);
+        emu.set_sound_timer(20);
+        emu.tick_timers();
+        let saved = (emu.delay_timer(), emu.sound_timer());
+        assert_eq!(saved, (29, 19));
+        for _ in 0..5 {
+            emu.tick_timers();
+        }
+        emu.set_delay_timer(saved.0);
+        emu.set_sound_timer(saved.1);
+        assert_eq!((emu.delay_t, emu.sound_t), saved);
+        emu.tick_timers();
+        assert_eq!((emu.delay_timer(), emu.sound_timer()), (28, 18));
+    }
+
+    #[test]
+    fn deltas_describe_each_tick() {
+        // LD V0, 5; LD I, 0x000; DRW V0, V0, 1
+        let mut emu = emu_with(&[0x60, 0x05, 0xA0, 0x00, 0xD0, 0x01]);
+        emu.set_delta_recording(true);
+        for _ in 0..3 {
+            emu.tick().unwrap();
+        }
+        let deltas = emu.take_deltas();
+        assert_eq!(deltas.len(), 3);
+        assert_eq!(deltas[0], StateDelta {
+            pc: 0x200,
+            opcode: 0x6005,
+            next_pc: 0x202,
+            changes: vec![StateChange::Register { index: 0, old: 0, new: 5 }]
+        });
+        assert!(deltas[1].changes.is_empty()); // I was already 0
+        let pixels: Vec<StateChange> = (5..9).map(|x| StateChange::Pixel { x, y: 5, on: true }).collect();
+        assert_eq!(deltas[2].changes, pixels);
+        assert!(emu.take_deltas().is_empty());
+    }
+
+    // V1 = 0x81 and V2 = 0x42 through a single shift, (V1, VF) afterwards
+    fn after_shift(op: u16, shift_uses_vy: bool) -> (u8, u8) {
+        let mut emu = emu_with(&op.to_be_bytes());
+        emu.set_quirks(Quirks { shift_uses_vy, ..Quirks::default() });
+        emu.v_registers[1] = 0x81;
+        emu.v_registers[2] = 0x42;
+        emu.tick().unwrap();
+        (emu.v_registers[1], emu.v_registers[0xF])
+    }
+
+    #[test]
+    fn shifts_in_place_by_default() {
+        assert_eq!(after_shift(0x8126, false), (0x40, 1));
+        assert_eq!(after_shift(0x812E, false), (0x02, 1));
+    }
+
+    #[test]
+    fn shifts_vy_with_the_cosmac_quirk() {
+        assert_eq!(after_shift(0x8126, true), (0x21, 0));
+        assert_eq!(after_shift(0x812E, true), (0x84, 0));
+    }
+
+    #[test]
+    fn ram_holds_the_font_and_rom() {
+        let emu = emu_with(&SUBROUTINE_ROM);
+        let ram = emu.ram();
+        assert_eq!(ram.len(), RAM_SIZE);
+        assert_eq!(ram[..FONTSET_SIZE], FONTSET);
+        assert_eq!(ram[0x200..0x200 + SUBROUTINE_ROM.len()], SUBROUTINE_ROM);
+    }
+
+    #[test]
+    fn vf_as_the_sprite_coordinate() {
+        // LD I, 0x000; DRW VF, VF, 1 twice, the second draw reads VF after the first set it to 0
+        let mut emu = emu_with(&[0xA0, 0x00, 0xDF, 0xF1, 0xDF, 0xF1]);
+        emu.v_registers[0xF] = 10;
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert!((10..14).all(|x| emu.display[display_index(x, 10)]));
+        assert_eq!(emu.v_registers[0xF], 0);
+        emu.tick().unwrap();
+        assert!((0..4).all(|x| emu.display[display_index(x, 0)]));
+        assert_eq!(emu.pixel_count(), 8);
+        assert_eq!(emu.v_registers[0xF], 0);
+    }
+
+    #[test]
+    fn vf_coordinate_then_collision() {
+        // LD I, 0x000; DRW VF, VF, 1 at (1, 1) over an already lit (1, 1)
+        let mut emu = emu_with(&[0xA0, 0x00, 0xDF, 0xF1]);
+        emu.v_registers[0xF] = 1;
+        emu.display[display_index(1, 1)] = true;
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert!(!emu.display[display_index(1, 1)]);
+        assert!((2..5).all(|x| emu.display[display_index(x, 1)]));
+        assert_eq!(emu.v_registers[0xF], 1);
+    }
+
+    #[test]
+    fn pipeline_view_breaks_down_the_opcode() {
+        let emu = emu_with(&[0x8A, 0xB4]);
+        let view = emu.pipeline_view();
+        assert_eq!(view.pc, 0x200);
+        assert_eq!(view.bytes, [0x8A, 0xB4]);
+        assert_eq!(view.opcode, 0x8AB4);
+        assert_eq!((view.d1, view.d2, view.d3, view.d4), (0x8, 0xA, 0xB, 0x4));
+        assert_eq!(view.decoded, Some(Opcode::AddReg { x: 0xA, y: 0xB }));
+    }
+
+    #[test]
+    fn store_and_load_over_the_running_code() {
+        // LD [I], VF; LD VF, [I] with I on the second instruction, V0 and V1 rewrite it as itself
+        let mut emu = emu_with(&[0xFF, 0x55, 0xFF, 0x65]);
+        let regs: [u8; NUM_REGISTERS] = std::array::from_fn(|idx| match idx {
+            0 => 0xFF,
+            1 => 0x65,
+            _ => (idx * 0x11) as u8
+        });
+        emu.v_registers = regs;
+        emu.i_register = 0x202;
+        emu.tick().unwrap();
+        assert_eq!(emu.ram[0x202..0x212], regs);
+
+        emu.v_registers = [0; NUM_REGISTERS];
+        emu.tick().unwrap(); // runs the FF65 it just wrote
+        assert_eq!(emu.v_registers, regs);
+    }
+
+    #[test]
+    fn quirk_profile_round_trips() {
+        let schip = Quirks {
+            wrap_x: false,
+            wrap_y: false,
+            shift_uses_vy: false,
+            i_wraps_on_sprite_read: false,
+            mask_i: true
+        };
+        assert_eq!(Quirks::from_profile_string(&schip.to_profile_string()), Ok(schip));
+        assert_eq!(Quirks::from_profile_string(" wrap_y = false "), Ok(Quirks { wrap_y: false, ..Quirks::default() }));
+    }
+
+    #[test]
+    fn quirk_profile_rejects_bad_entries() {
+        assert_eq!(Quirks::from_profile_string("wrap_z=true"), Err(Chip8Error::BadQuirkProfile));
+        assert_eq!(Quirks::from_profile_string("wrap_x=yes"), Err(Chip8Error::BadQuirkProfile));
+        assert_eq!(Quirks::from_profile_string("wrap_x"), Err(Chip8Error::BadQuirkProfile));
+    }
+
+    #[test]
+    fn checkerboard_pattern() {
+        let mut emu = Emulator::new();
+        emu.load_test_pattern(TestPattern::Checkerboard);
+        assert!(emu.display[display_index(0, 0)]);
+        assert!(!emu.display[display_index(1, 0)]);
+        assert!(!emu.display[display_index(0, 1)]);
+        assert!(emu.display[display_index(SCREEN_WIDTH - 1, SCREEN_HEIGHT - 1)]);
+        assert_eq!(emu.pixel_count(), SCREEN_WIDTH * SCREEN_HEIGHT / 2);
+        assert!(emu.is_display_dirty());
+    }
+
+    #[test]
+    fn border_and_solid_patterns() {
+        let mut emu = Emulator::new();
+        emu.load_test_pattern(TestPattern::Border);
+        assert_eq!(emu.pixel_count(), 2 * SCREEN_WIDTH + 2 * SCREEN_HEIGHT - 4);
+        emu.load_test_pattern(TestPattern::AllOn);
+        assert_eq!(emu.pixel_count(), SCREEN_WIDTH * SCREEN_HEIGHT);
+        emu.load_test_pattern(TestPattern::AllOff);
+        assert_eq!(emu.pixel_count(), 0);
+    }
+
+    #[test]
+    fn call_depth_follows_calls_and_returns() {
+        let mut emu = emu_with(&SUBROUTINE_ROM);
+        assert_eq!(emu.call_depth(), 0);
+        emu.tick().unwrap();
+        assert_eq!(emu.call_depth(), 1);
+        for _ in 0..3 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(emu.call_depth(), 0);
+    }
+
+    // I after ADD I, V0 with I = 0xFFF and V0 = 2
+    fn i_after_overflowing_add(mask_i: bool) -> u16 {
+        let mut emu = emu_with(&[0xF0, 0x1E]);
+        emu.set_quirks(Quirks { mask_i, ..Quirks::default() });
+        emu.i_register = 0xFFF;
+        emu.v_registers[0] = 2;
+        emu.tick().unwrap();
+        emu.i_register
+    }
+
+    #[test]
+    fn mask_i_wraps_fx1e_at_12_bits() {
+        assert_eq!(i_after_overflowing_add(true), 0x001);
+        assert_eq!(i_after_overflowing_add(false), 0x1001);
+    }
+
+    #[test]
+    fn subroutine_program_keeps_the_invariants() {
+        // check_invariants runs after every tick in test builds, so any broken one panics here
+        let mut emu = emu_with(&SUBROUTINE_ROM);
+        for _ in 0..50 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(emu.call_depth(), 0);
+    }
+
+    #[test]
+    fn jump_call_and_skip_past_the_end_of_ram_error() {
+        for rom in [[0x1F, 0xFF], [0x2F, 0xFF]] {
+            let mut emu = emu_with(&rom);
+            assert_eq!(emu.tick(), Err(Chip8Error::PcOutOfBounds));
+            assert_eq!(emu.pc, START_ADDR);
+            assert_eq!(emu.call_depth(), 0);
+        }
+
+        // SE V0, 0 as the last instruction in ram, skipping would land past the end
+        let mut emu = emu_with(&[0x1F, 0xFE]);
+        emu.ram[0xFFE..].copy_from_slice(&[0x30, 0x00]);
+        emu.tick().unwrap();
+        assert_eq!(emu.tick(), Err(Chip8Error::PcOutOfBounds));
+        assert_eq!(emu.pc, 0xFFE);
+    }
+
+    #[test]
+    fn falling_off_the_end_of_ram_errors() {
+        // JP 0xFFE onto a NOP
+        let mut emu = emu_with(&[0x1F, 0xFE]);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert_eq!(emu.pc as usize, RAM_SIZE);
+        assert_eq!(emu.tick(), Err(Chip8Error::PcOutOfBounds));
+        assert_eq!(emu.pipeline_view().opcode, 0x0000);
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[test]
+    fn corrupted_rom_byte_is_an_unknown_opcode() {
+        let mut emu = emu_with(&SUBROUTINE_ROM);
+        emu.corrupt_ram(0x200, 0xFF);
+        assert_eq!(emu.tick(), Err(Chip8Error::UnknownOpcode(0xFF06)));
+        assert_eq!(emu.pc, START_ADDR);
+        emu.corrupt_ram(RAM_SIZE as u16 + 0x200, 0x00); // wraps back onto 0x200
+        assert_eq!(emu.ram[0x200], 0x00);
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[test]
+    fn corrupt_random_is_seeded() {
+        let mut a = emu_with(&SUBROUTINE_ROM);
+        let mut b = emu_with(&SUBROUTINE_ROM);
+        a.set_rng_seed(7);
+        b.set_rng_seed(7);
+        a.corrupt_random(20);
+        b.corrupt_random(20);
+        assert_eq!(a.ram, b.ram);
+        assert_ne!(a.ram, emu_with(&SUBROUTINE_ROM).ram);
+    }
+
+    #[test]
+    fn frame_budget_is_the_rest_of_the_frame() {
+        let emu = Emulator::new();
+        let period = Duration::from_secs(1) / 60;
+        assert_eq!(emu.frame_budget(60, Duration::from_millis(10)), period - Duration::from_millis(10));
+        assert_eq!(emu.frame_budget(60, Duration::from_millis(20)), Duration::ZERO);
+        assert_eq!(emu.frame_budget(0, Duration::ZERO), Duration::ZERO);
+    }
+
+    #[test]
+    fn changed_registers_marks_only_written_registers() {
+        // LD V3, 7; LD V3, 7; ADD V3, V4
+        let mut emu = emu_with(&[0x63, 0x07, 0x63, 0x07, 0x83, 0x44]);
+        emu.tick().unwrap();
+        assert_eq!(emu.changed_registers(), 1 << 3);
+        emu.tick().unwrap();
+        assert_eq!(emu.changed_registers(), 0); // same value written again
+        emu.v_registers[4] = 0xFF;
+        emu.tick().unwrap();
+        assert_eq!(emu.changed_registers(), (1 << 3) | (1 << 0xF));
+    }
+
+    #[test]
+    fn sprite_rows_wrap_to_the_start_of_ram_with_the_quirk() {
+        // DRW V0, V0, 4 with I = 0xFFE, the last two rows come from the "0" glyph at 0x000
+        let mut emu = emu_with(&[0xD0, 0x04]);
+        emu.ram[0xFFE..].copy_from_slice(&[0xFF, 0x81]);
+        emu.i_register = 0xFFE;
+        emu.set_quirks(Quirks { i_wraps_on_sprite_read: true, ..Quirks::default() });
+        emu.tick().unwrap();
+        let row = |emu: &Emulator, y| (0..8).filter(|&x| emu.display[display_index(x, y)]).count();
+        assert_eq!([row(&emu, 0), row(&emu, 1), row(&emu, 2), row(&emu, 3)], [8, 2, 4, 2]);
+
+        let mut emu = emu_with(&[0xD0, 0x04]);
+        emu.i_register = 0xFFE;
+        assert_eq!(emu.tick(), Err(Chip8Error::MemoryOutOfBounds));
+        assert_eq!(emu.pixel_count(), 0);
+    }
+
+    #[test]
+    fn pixel_count_after_clear_and_draw() {
+        // CLS; LD V0, 8; LD F, V0; DRW V0, V0, 5
+        let mut emu = emu_with(&[0x00, 0xE0, 0x60, 0x08, 0xF0, 0x29, 0xD0, 0x05]);
+        emu.load_test_pattern(TestPattern::AllOn);
+        emu.tick().unwrap();
+        assert_eq!(emu.pixel_count(), 0);
+        for _ in 0..3 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(emu.pixel_count(), 16); // the "8" glyph
+    }
+
+    #[test]
+    fn read_u16_in_both_byte_orders() {
+        let mut emu = Emulator::new();
+        emu.ram[0x300..0x304].copy_from_slice(&[0x12, 0x34, 0xAB, 0xCD]);
+        assert_eq!(emu.read_u16(0x300, Endian::Big), Ok(0x1234));
+        assert_eq!(emu.read_u16(0x300, Endian::Little), Ok(0x3412));
+        assert_eq!(emu.read_u16_slice(0x300, 2, Endian::Little), Ok(vec![0x3412, 0xCDAB]));
+        assert_eq!(emu.read_u16(0xFFF, Endian::Big), Err(Chip8Error::MemoryOutOfBounds));
+    }
+
+    #[test]
+    fn bench_rom_runs_the_requested_cycles() {
+        // RND V0, 0xFF; JP 0x200
+        let rom = [0xC0, 0xFF, 0x12, 0x00];
+        let result = bench_rom(&rom, 10_000, 1).unwrap();
+        assert_eq!(result.cycles, 10_000);
+        assert!(result.elapsed > Duration::ZERO);
+        assert_eq!(result.opcode_histogram, HashMap::from([(0x1, 5_000), (0xC, 5_000)]));
+    }
+
+    #[test]
+    fn input_provider_answers_key_waits() {
+        // LD V3, K
+        let mut emu = emu_with(&[0xF3, 0x0A]);
+        emu.set_input_provider(Box::new(|key| key == 5));
+        emu.tick().unwrap();
+        assert_eq!(emu.v_registers[3], 5);
+        assert!(!emu.is_waiting_for_key());
+    }
+
+    #[test]
+    fn key_skips_only_ask_about_real_keys() {
+        use std::sync::{Arc, Mutex};
+
+        let asked = Arc::new(Mutex::new(Vec::new()));
+        let provider_asked = Arc::clone(&asked);
+        // SKP V1; NOP; SKNP V1
+        let mut emu = emu_with(&[0xE1, 0x9E, 0x00, 0x00, 0xE1, 0xA1]);
+        emu.set_input_provider(Box::new(move |key| {
+            provider_asked.lock().unwrap().push(key);
+            key == 5
+        }));
+        emu.v_registers[1] = 0xF5;
+        emu.tick().unwrap();
+        assert_eq!(emu.pc, START_ADDR + 4); // 0xF5 reads key 5
+        emu.tick().unwrap();
+        assert_eq!(emu.pc, START_ADDR + 6);
+        assert_eq!(*asked.lock().unwrap(), vec![5, 5]);
+    }
+
+    // result of LD [I], V3 with font protection on
+    fn protected_store_at(i: u16) -> Result<(), Chip8Error> {
+        let mut emu = emu_with(&[0xF3, 0x55]);
+        emu.set_font_protection(true);
+        emu.v_registers[..4].copy_from_slice(&[1, 2, 3, 4]);
+        emu.i_register = i;
+        let result = emu.tick();
+        assert_eq!(emu.ram[..FONTSET_SIZE], FONTSET);
+        result
+    }
+
+    #[test]
+    fn font_protection_covers_the_whole_store() {
+        assert_eq!(protected_store_at(0x000), Err(Chip8Error::ProtectedRegion));
+        assert_eq!(protected_store_at((FONTSET_SIZE - 2) as u16), Err(Chip8Error::ProtectedRegion));
+        assert_eq!(protected_store_at(FONTSET_SIZE as u16), Ok(()));
+    }
+
+    #[test]
+    fn tick_traced_reports_each_instruction() {
+        let mut emu = emu_with(&SUBROUTINE_ROM);
+        assert_eq!(emu.tick_traced(), Ok(Some((0x200, 0x2206))));
+        assert_eq!(emu.tick_traced(), Ok(Some((0x206, 0x6101))));
+    }
+
+    #[test]
+    fn tick_traced_is_none_once_halted() {
+        // RET with nothing to return to
+        let mut emu = emu_with(&[0x00, 0xEE]);
+        emu.set_halt_on_stack_error(true);
+        assert_eq!(emu.tick_traced(), Ok(None));
+        assert!(emu.is_halted());
+        assert_eq!(emu.tick_traced(), Ok(None));
+    }
+
+    #[test]
+    fn reset_can_keep_the_display() {
+        let mut emu = emu_with(&DOUBLE_DRAW_ROM);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        let screen = emu.display;
+        emu.keypress(3, true);
+        emu.reset_options(ResetOptions { keep_display: true, keep_rom: true, keep_keys: false });
+        assert_eq!(emu.display, screen);
+        assert_eq!(emu.pc, START_ADDR);
+        assert_eq!(emu.i_register, 0);
+        assert!(!emu.keys[3]);
+        assert_eq!(emu.ram[0x200..0x206], DOUBLE_DRAW_ROM);
+    }
+
+    #[test]
+    fn reset_without_options_clears_everything() {
+        let mut emu = emu_with(&DOUBLE_DRAW_ROM);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        emu.keypress(3, true);
+        emu.reset_options(ResetOptions::default());
+        assert_eq!(emu.pixel_count(), 0);
+        assert!(!emu.keys[3]);
+        assert!(emu.ram[0x200..0x206].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn display_index_and_coords_round_trip() {
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (63, 0), (10, 20), (SCREEN_WIDTH - 1, SCREEN_HEIGHT - 1)] {
+            assert_eq!(display_coords(display_index(x, y)), (x, y));
+        }
+        assert_eq!(display_index(0, 1), SCREEN_WIDTH);
+        assert_eq!(display_coords(SCREEN_WIDTH * SCREEN_HEIGHT - 1), (SCREEN_WIDTH - 1, SCREEN_HEIGHT - 1));
+    }
+
+    #[test]
+    fn verify_rom_against_rom_hash() {
+        let emu = emu_with(&SUBROUTINE_ROM);
+        let hash = emu.rom_hash();
+        assert!(verify_rom(&SUBROUTINE_ROM, hash));
+        let mut mutated = SUBROUTINE_ROM;
+        mutated[5] ^= 1;
+        assert!(!verify_rom(&mutated, hash));
+        assert!(!verify_rom(&SUBROUTINE_ROM[..10], hash));
+    }
+
+    #[test]
+    fn stuck_loop_fires_on_a_loop_that_changes_nothing() {
+        // LD V0, 1; LD V1, 2; JP 0x200
+        let mut emu = emu_with(&[0x60, 0x01, 0x61, 0x02, 0x12, 0x00]);
+        emu.v_registers[..2].copy_from_slice(&[1, 2]); // so the first lap is the same as the rest
+        emu.set_stuck_loop_limit(Some(3));
+        for _ in 0..9 {
+            emu.tick().unwrap(); // three laps, each state seen three times
+        }
+        assert_eq!(emu.tick(), Err(Chip8Error::StuckLoop));
+    }
+
+    #[test]
+    fn stuck_loop_limit_of_one_allows_each_state_once() {
+        let mut emu = emu_with(&[0x12, 0x00]);
+        emu.set_stuck_loop_limit(Some(1));
+        emu.tick().unwrap();
+        assert_eq!(emu.tick(), Err(Chip8Error::StuckLoop));
+    }
+
+    #[test]
+    fn key_wait_is_not_a_stuck_loop() {
+        // LD V0, K
+        let mut emu = emu_with(&[0xF0, 0x0A]);
+        emu.set_stuck_loop_limit(Some(1));
+        for _ in 0..100 {
+            emu.tick().unwrap();
+        }
+        assert!(emu.is_waiting_for_key());
+    }
+
+    #[test]
+    fn current_nibbles_split_the_opcode_at_pc() {
+        let emu = emu_with(&[0xAB, 0xCD]);
+        assert_eq!(emu.current_nibbles(), (0xA, 0xB, 0xC, 0xD));
+    }
+
+    #[test]
+    fn extension_opcodes_are_reported_as_such() {
+        for op in [0xD120u16, 0x00FF, 0xF030] {
+            let mut emu = emu_with(&op.to_be_bytes());
+            assert_eq!(emu.tick(), Err(Chip8Error::OpcodeNotSupportedOnPlatform(op)));
+        }
+        let mut emu = emu_with(&[0x5A, 0xB7]);
+        assert_eq!(emu.tick(), Err(Chip8Error::UnknownOpcode(0x5AB7)));
+    }
+
+    #[test]
+    fn step_frame_runs_a_frame_of_instructions() {
+        let mut emu = emu_with(&DRAW_AFTER_NOPS_ROM);
+        emu.sound_t = 1;
+        assert_eq!(emu.step_frame(), Ok(FrameInfo {
+            instructions: DEFAULT_CPU_PER_TIMER as u64,
+            beep_started: true,
+            display_changed: true
+        }));
+        assert_eq!(emu.frame(), 1);
+
+        // the frontend hasn't cleared the first frame's redraw
+        let info = emu.step_frame().unwrap();
+        assert!(!info.display_changed && !info.beep_started);
+        assert!(emu.is_display_dirty());
+    }
+
+    #[test]
+    fn set_cycles_per_frame_changes_the_frame_length() {
+        let mut emu = emu_with(&DRAW_AFTER_NOPS_ROM);
+        emu.set_cycles_per_frame(3);
+        let info = emu.step_frame().unwrap();
+        assert_eq!(info.instructions, 3);
+        assert!(!info.display_changed);
+        assert_eq!(emu.pc, START_ADDR + 6);
+    }
+
+    #[test]
+    fn failed_swap_keeps_the_running_game() {
+        let mut emu = emu_with(&SUBROUTINE_ROM);
+        emu.tick().unwrap();
+        let hash = emu.rom_hash();
+        assert_eq!(emu.swap_rom(&[]), Err(Chip8Error::EmptyRom));
+        assert_eq!(emu.swap_rom(&vec![0; RAM_SIZE]), Err(Chip8Error::RomTooLarge));
+        assert_eq!(emu.rom_hash(), hash);
+        assert_eq!(emu.rom_len, SUBROUTINE_ROM.len());
+        assert_eq!(emu.pc, 0x206);
+        assert_eq!(emu.call_depth(), 1);
+    }
+
+    fn _assert_send<T: Send>() {}
+
+    #[test]
+    fn emulator_is_send() {
+        _assert_send::<Emulator>();
+    }
+
+    #[test]
+    fn deferred_draws_run_the_hook_once() {
+        use std::sync::{Arc, Mutex};
+
+        let calls = Arc::new(Mutex::new(0));
+        let hook_calls = Arc::clone(&calls);
+        let mut emu = emu_with(&DOUBLE_DRAW_ROM);
+        emu.set_max_draws_per_frame(Some(1));
+        emu.on_opcode(0xD, Box::new(move |_, _| *hook_calls.lock().unwrap() += 1));
+        for _ in 0..10 {
+            emu.tick().unwrap(); // the second draw keeps being held back
+        }
+        assert_eq!(*calls.lock().unwrap(), 1);
+        emu.tick_timers();
+        emu.tick().unwrap();
+        assert_eq!(*calls.lock().unwrap(), 2);
+        assert_eq!(emu.pc, START_ADDR + 6);
+    }
+
+    #[test]
+    fn turning_mask_i_back_on_masks_a_wide_i() {
+        // ADD I, V0 twice
+        let mut emu = emu_with(&[0xF0, 0x1E, 0xF0, 0x1E]);
+        emu.set_quirks(Quirks { mask_i: false, ..Quirks::default() });
+        emu.i_register = 0xFFF;
+        emu.v_registers[0] = 2;
+        emu.tick().unwrap();
+        assert_eq!(emu.i_register, 0x1001);
+        emu.set_quirks(Quirks::default());
+        assert_eq!(emu.i_register, 0x001);
+        emu.tick().unwrap(); // check_invariants would panic on an unmasked I here
+        assert_eq!(emu.i_register, 0x003);
+    }
+
+    #[test]
+    fn changed_registers_clear_when_nothing_runs() {
+        // LD V2, 9 then halted before the next instruction
+        let mut emu = emu_with(&[0x62, 0x09, 0x62, 0x08]);
+        emu.tick().unwrap();
+        assert_eq!(emu.changed_registers(), 1 << 2);
+        emu.halted = true;
+        emu.tick().unwrap();
+        assert_eq!(emu.changed_registers(), 0);
+        assert_eq!(emu.v_registers[2], 9);
+    }
+}
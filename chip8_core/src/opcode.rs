@@ -0,0 +1,252 @@
+use std::fmt;
+
+use crate::{RAM_SIZE, START_ADDR};
+
+// decoded form of every opcode the emulator executes, x/y are register indexes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Nop,                          // 0000
+    Cls,                          // 00E0
+    Ret,                          // 00EE
+    Jmp(u16),                     // 1NNN
+    Call(u16),                    // 2NNN
+    SkipEqImm { x: u8, nn: u8 },  // 3XNN
+    SkipNeImm { x: u8, nn: u8 },  // 4XNN
+    SkipEqReg { x: u8, y: u8 },   // 5XY0
+    SetImm { x: u8, nn: u8 },     // 6XNN
+    AddImm { x: u8, nn: u8 },     // 7XNN
+    SetReg { x: u8, y: u8 },      // 8XY0
+    Or { x: u8, y: u8 },          // 8XY1
+    And { x: u8, y: u8 },         // 8XY2
+    Xor { x: u8, y: u8 },         // 8XY3
+    AddReg { x: u8, y: u8 },      // 8XY4
+    SubReg { x: u8, y: u8 },      // 8XY5
+    Shr { x: u8, y: u8 },         // 8XY6
+    SubN { x: u8, y: u8 },        // 8XY7
+    Shl { x: u8, y: u8 },         // 8XYE
+    SkipNeReg { x: u8, y: u8 },   // 9XY0
+    SetI(u16),                    // ANNN
+    JmpV0(u16),                   // BNNN
+    Rand { x: u8, nn: u8 },       // CXNN
+    Draw { x: u8, y: u8, n: u8 }, // DXYN
+    SkipKey(u8),                  // EX9E
+    SkipNotKey(u8),               // EXA1
+    GetDelay(u8),                 // FX07
+    WaitKey(u8),                  // FX0A
+    SetDelay(u8),                 // FX15
+    SetSound(u8),                 // FX18
+    AddI(u8),                     // FX1E
+    Font(u8),                     // FX29
+    Bcd(u8),                      // FX33
+    Store(u8),                    // FX55
+    Load(u8),                     // FX65
+}
+
+impl Opcode {
+    // mirrors the match in Emulator::execute, None for anything execute doesn't implement
+    pub fn decode(op: u16) -> Option<Self> {
+        let d1 = (op & 0xF000) >> 12;
+        let x = ((op & 0x0F00) >> 8) as u8;
+        let y = ((op & 0x00F0) >> 4) as u8;
+        let n = (op & 0x000F) as u8;
+        let nn = (op & 0xFF) as u8;
+        let nnn = op & 0xFFF;
+
+        let decoded = match (d1, x, y, n) {
+            (0,0,0,0) => Opcode::Nop,
+            (0,0,0xE,0) => Opcode::Cls,
+            (0,0,0xE,0xE) => Opcode::Ret,
+            (1,_,_,_) => Opcode::Jmp(nnn),
+            (2,_,_,_) => Opcode::Call(nnn),
+            (3,_,_,_) => Opcode::SkipEqImm { x, nn },
+            (4,_,_,_) => Opcode::SkipNeImm { x, nn },
+            (5,_,_,0) => Opcode::SkipEqReg { x, y },
+            (6,_,_,_) => Opcode::SetImm { x, nn },
+            (7,_,_,_) => Opcode::AddImm { x, nn },
+            (8,_,_,0) => Opcode::SetReg { x, y },
+            (8,_,_,1) => Opcode::Or { x, y },
+            (8,_,_,2) => Opcode::And { x, y },
+            (8,_,_,3) => Opcode::Xor { x, y },
+            (8,_,_,4) => Opcode::AddReg { x, y },
+            (8,_,_,5) => Opcode::SubReg { x, y },
+            (8,_,_,6) => Opcode::Shr { x, y },
+            (8,_,_,7) => Opcode::SubN { x, y },
+            (8,_,_,0xE) => Opcode::Shl { x, y },
+            (9,_,_,0) => Opcode::SkipNeReg { x, y },
+            (0xA,_,_,_) => Opcode::SetI(nnn),
+            (0xB,_,_,_) => Opcode::JmpV0(nnn),
+            (0xC,_,_,_) => Opcode::Rand { x, nn },
+            (0xD,_,_,0) => return None, // SCHIP 16x16 sprite
+            (0xD,_,_,_) => Opcode::Draw { x, y, n },
+            (0xE,_,9,0xE) => Opcode::SkipKey(x),
+            (0xE,_,0xA,1) => Opcode::SkipNotKey(x),
+            (0xF,_,0,7) => Opcode::GetDelay(x),
+            (0xF,_,0,0xA) => Opcode::WaitKey(x),
+            (0xF,_,1,5) => Opcode::SetDelay(x),
+            (0xF,_,1,8) => Opcode::SetSound(x),
+            (0xF,_,1,0xE) => Opcode::AddI(x),
+            (0xF,_,2,9) => Opcode::Font(x),
+            (0xF,_,3,3) => Opcode::Bcd(x),
+            (0xF,_,5,5) => Opcode::Store(x),
+            (0xF,_,6,5) => Opcode::Load(x),
+            (_, _, _, _) => return None
+        };
+        Some(decoded)
+    }
+
+    // mnemonic alone, matches the first word of the Display form
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Opcode::Nop => "NOP",
+            Opcode::Cls => "CLS",
+            Opcode::Ret => "RET",
+            Opcode::Jmp(_) | Opcode::JmpV0(_) => "JMP",
+            Opcode::Call(_) => "CALL",
+            Opcode::SkipEqImm { .. } | Opcode::SkipEqReg { .. } => "SE",
+            Opcode::SkipNeImm { .. } | Opcode::SkipNeReg { .. } => "SNE",
+            Opcode::SetImm { .. } | Opcode::SetReg { .. } | Opcode::SetI(_) | Opcode::GetDelay(_) | Opcode::WaitKey(_) |
+            Opcode::SetDelay(_) | Opcode::SetSound(_) | Opcode::Font(_) | Opcode::Bcd(_) | Opcode::Store(_) | Opcode::Load(_) => "LD",
+            Opcode::AddImm { .. } | Opcode::AddReg { .. } | Opcode::AddI(_) => "ADD",
+            Opcode::Or { .. } => "OR",
+            Opcode::And { .. } => "AND",
+            Opcode::Xor { .. } => "XOR",
+            Opcode::SubReg { .. } => "SUB",
+            Opcode::Shr { .. } => "SHR",
+            Opcode::SubN { .. } => "SUBN",
+            Opcode::Shl { .. } => "SHL",
+            Opcode::Rand { .. } => "RND",
+            Opcode::Draw { .. } => "DRW",
+            Opcode::SkipKey(_) => "SKP",
+            Opcode::SkipNotKey(_) => "SKNP",
+        }
+    }
+}
+
+// assembly style mnemonic with operands, e.g. "ADD V1, 0x05"
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Opcode::Nop => write!(f, "NOP"),
+            Opcode::Cls => write!(f, "CLS"),
+            Opcode::Ret => write!(f, "RET"),
+            Opcode::Jmp(nnn) => write!(f, "JMP 0x{:03X}", nnn),
+            Opcode::Call(nnn) => write!(f, "CALL 0x{:03X}", nnn),
+            Opcode::SkipEqImm { x, nn } => write!(f, "SE V{:X}, 0x{:02X}", x, nn),
+            Opcode::SkipNeImm { x, nn } => write!(f, "SNE V{:X}, 0x{:02X}", x, nn),
+            Opcode::SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Opcode::SetImm { x, nn } => write!(f, "LD V{:X}, 0x{:02X}", x, nn),
+            Opcode::AddImm { x, nn } => write!(f, "ADD V{:X}, 0x{:02X}", x, nn),
+            Opcode::SetReg { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Opcode::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Opcode::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Opcode::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Opcode::AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Opcode::SubReg { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Opcode::Shr { x, .. } => write!(f, "SHR V{:X}", x),
+            Opcode::SubN { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Opcode::Shl { x, .. } => write!(f, "SHL V{:X}", x),
+            Opcode::SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Opcode::SetI(nnn) => write!(f, "LD I, 0x{:03X}", nnn),
+            Opcode::JmpV0(nnn) => write!(f, "JMP V0, 0x{:03X}", nnn),
+            Opcode::Rand { x, nn } => write!(f, "RND V{:X}, 0x{:02X}", x, nn),
+            Opcode::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Opcode::SkipKey(x) => write!(f, "SKP V{:X}", x),
+            Opcode::SkipNotKey(x) => write!(f, "SKNP V{:X}", x),
+            Opcode::GetDelay(x) => write!(f, "LD V{:X}, DT", x),
+            Opcode::WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            Opcode::SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            Opcode::SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            Opcode::AddI(x) => write!(f, "ADD I, V{:X}", x),
+            Opcode::Font(x) => write!(f, "LD F, V{:X}", x),
+            Opcode::Bcd(x) => write!(f, "LD B, V{:X}", x),
+            Opcode::Store(x) => write!(f, "LD [I], V{:X}", x),
+            Opcode::Load(x) => write!(f, "LD V{:X}, [I]", x),
+        }
+    }
+}
+
+// mnemonic for any raw opcode, unknown opcodes render as their raw hex
+pub fn disassemble(op: u16) -> String {
+    match Opcode::decode(op) {
+        Some(decoded) => decoded.to_string(),
+        None => format!("??? 0x{:04X}", op)
+    }
+}
+
+// opcodes SCHIP or XO-CHIP define that plain CHIP-8 doesn't, so they can be reported as such rather than as garbage
+pub(crate) fn is_extension_opcode(op: u16) -> bool {
+    let x = (op & 0x0F00) >> 8;
+    let nn = op & 0xFF;
+    match op >> 12 {
+        0x0 => x == 0 && (matches!(nn >> 4, 0xC | 0xD) || matches!(nn, 0xFB..=0xFF)), // scroll, exit, lores/hires
+        0x5 => matches!(op & 0xF, 2 | 3), // XO-CHIP register range save/load
+        0xD => op & 0xF == 0, // SCHIP 16x16 sprite
+        0xF => matches!(op, 0xF000 | 0xF002) || matches!(nn, 0x01 | 0x30 | 0x3A | 0x75 | 0x85), // long I, planes, audio, big font, flags
+        _ => false
+    }
+}
+
+// mnemonic without operands for any raw opcode, "???" if it doesn't decode
+pub fn opcode_name(op: u16) -> &'static str {
+    Opcode::decode(op).map_or("???", |decoded| decoded.name())
+}
+
+// (address, raw opcode, decoded) for each two byte slot of a rom, addresses from START_ADDR
+// linear like Emulator::upcoming so data shows up too, a trailing odd byte is left out
+// anything that wouldn't fit in ram above START_ADDR is left out too, so the addresses can't overflow
+pub fn instructions(bytes: &[u8]) -> impl Iterator<Item = (u16, u16, Option<Opcode>)> + '_ {
+    bytes.chunks_exact(2).take((RAM_SIZE - START_ADDR as usize) / 2).enumerate().map(|(idx, pair)| {
+        let op = u16::from_be_bytes([pair[0], pair[1]]);
+        (START_ADDR + 2 * idx as u16, op, Opcode::decode(op))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instructions_number_slots_from_the_start_address() {
+        let listed: Vec<_> = instructions(&[0x00, 0xE0, 0x12, 0x00, 0xFF]).collect();
+        assert_eq!(listed, vec![
+            (0x200, 0x00E0, Some(Opcode::Cls)),
+            (0x202, 0x1200, Some(Opcode::Jmp(0x200)))
+        ]);
+    }
+
+    #[test]
+    fn instructions_stop_at_the_end_of_ram() {
+        let bytes = vec![0x00; 0x10000];
+        let last = instructions(&bytes).last().unwrap();
+        assert_eq!(last.0, (RAM_SIZE - 2) as u16);
+        assert_eq!(instructions(&bytes).count(), (RAM_SIZE - START_ADDR as usize) / 2);
+    }
+
+    #[test]
+    fn opcode_name_is_the_bare_mnemonic() {
+        assert_eq!(opcode_name(0xD125), "DRW");
+        assert_eq!(opcode_name(0x8124), "ADD");
+        assert_eq!(opcode_name(0xF133), "LD");
+        assert_eq!(opcode_name(0xB200), "JMP");
+        assert_eq!(opcode_name(0xD120), "???");
+    }
+
+    #[test]
+    fn name_matches_the_first_word_of_the_listing() {
+        for op in (0..=0xFFFFu16).step_by(7) {
+            if let Some(decoded) = Opcode::decode(op) {
+                assert_eq!(decoded.to_string().split_whitespace().next(), Some(decoded.name()), "{:04X}", op);
+            }
+        }
+    }
+
+    #[test]
+    fn extension_opcodes() {
+        assert!(is_extension_opcode(0xD120)); // SCHIP 16x16 sprite
+        assert!(is_extension_opcode(0x00C4)); // scroll down
+        assert!(is_extension_opcode(0x5122)); // XO-CHIP save range
+        assert!(is_extension_opcode(0xF000)); // long I
+        assert!(!is_extension_opcode(0xD125));
+        assert!(!is_extension_opcode(0x5AB7));
+    }
+}
@@ -0,0 +1,74 @@
+use crate::{display_index, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// compare a display against ascii art, '#' is a lit pixel and '.' is off
+// whitespace around each line and blank lines are ignored so the art can be indented in a raw string
+// anything outside the rows/columns given is expected to be off
+// panics with the expected and actual screens side by side, mismatched rows marked with '!'
+pub fn assert_display_eq(actual: &[bool], expected_ascii: &str) {
+    assert_eq!(actual.len(), SCREEN_WIDTH * SCREEN_HEIGHT, "display has the wrong number of pixels");
+
+    let mut expected = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+    let rows = expected_ascii.lines().map(|line| line.trim()).filter(|line| !line.is_empty());
+    for (y, line) in rows.enumerate() {
+        assert!(y < SCREEN_HEIGHT, "expected art has more than {} rows", SCREEN_HEIGHT);
+        for (x, c) in line.chars().enumerate() {
+            assert!(x < SCREEN_WIDTH, "expected art row {} is wider than {}", y, SCREEN_WIDTH);
+            expected[display_index(x, y)] = match c {
+                '#' => true,
+                '.' => false,
+                _ => panic!("unexpected character {:?} in expected art, use '#' or '.'", c)
+            };
+        }
+    }
+
+    if actual == &expected[..] {
+        return;
+    }
+
+    let render = |pixels: &[bool]| -> String { pixels.iter().map(|&on| if on { '#' } else { '.' }).collect() };
+    let mut diff = String::from("display mismatch (expected | actual)\n");
+    for (want, got) in expected.chunks(SCREEN_WIDTH).zip(actual.chunks(SCREEN_WIDTH)) {
+        let marker = if want == got { ' ' } else { '!' };
+        diff.push_str(&format!("{} {} | {}\n", marker, render(want), render(got)));
+    }
+    panic!("{}", diff);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen_with(pixels: &[(usize, usize)]) -> Vec<bool> {
+        let mut display = vec![false; SCREEN_WIDTH * SCREEN_HEIGHT];
+        for &(x, y) in pixels {
+            display[display_index(x, y)] = true;
+        }
+        display
+    }
+
+    #[test]
+    fn matching_art_passes() {
+        let display = screen_with(&[(1, 0), (0, 1), (2, 1)]);
+        assert_display_eq(&display, "
+            .#.
+            #.#
+        ");
+    }
+
+    #[test]
+    fn mismatch_reports_a_diff() {
+        let display = screen_with(&[(0, 0)]);
+        let result = std::panic::catch_unwind(|| assert_display_eq(&display, ".#"));
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.starts_with("display mismatch (expected | actual)"));
+        let first_row = message.lines().nth(1).unwrap();
+        assert!(first_row.starts_with("! .#....") && first_row.contains("| #....."), "{}", first_row);
+        assert!(message.lines().nth(2).unwrap().starts_with("  "), "matching rows are not marked");
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected character")]
+    fn bad_art_characters_panic() {
+        assert_display_eq(&screen_with(&[]), "#x");
+    }
+}
@@ -37,11 +37,10 @@ fn main() {
 
     println!("Creating emulator for {}", &args[1]);
 
-    let mut chip8 = Emulator::new();
     let mut rom = File::open(&args[1]).expect("Unable to open file");
     let mut buffer = Vec::new();
     rom.read_to_end(&mut buffer).unwrap();
-    chip8.load(&buffer);
+    let mut chip8 = Emulator::from_rom(&buffer).expect("Unable to load rom");
 
     let mut event_pump = sdl_context.event_pump().unwrap();
     'gameloop: loop {
@@ -64,7 +63,10 @@ fn main() {
             }
         }
         for _ in 0..TICKS_PER_FRAME {
-            chip8.tick();
+            if let Err(err) = chip8.tick() {
+                println!("Emulator stopped: {}", err);
+                break 'gameloop;
+            }
         }
         chip8.tick_timers();
         draw_screen(&chip8, &mut canvas); 